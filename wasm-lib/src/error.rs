@@ -50,7 +50,7 @@ macro_rules! make_error {
 
 pub(crate) use make_error;
 
-use crate::span::Span;
+use crate::{source_map::SourceMap, span::Span};
 
 use wasm_bindgen::prelude::*;
 
@@ -60,14 +60,14 @@ pub enum ReportType {
     Error,
     Warning,
 }
-// impl ReportType {
-//     pub fn display_str(self) -> String {
-//         match self {
-//             ReportType::Error => "error:".bright_red().bold().to_string(),
-//             ReportType::Warning => "warning:".bright_yellow().bold().to_string(),
-//         }
-//     }
-// }
+impl ReportType {
+    pub fn display_str(self) -> &'static str {
+        match self {
+            ReportType::Error => "error",
+            ReportType::Warning => "warning",
+        }
+    }
+}
 
 #[wasm_bindgen]
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -83,7 +83,7 @@ impl Report {
         self.title.clone()
     }
     pub fn get_typ(&self) -> ReportType {
-        self.typ.clone()
+        self.typ
     }
     pub fn get_msg_spans(&self) -> Vec<Span> {
         self.messages.iter().map(|v| v.0).collect()
@@ -91,4 +91,39 @@ impl Report {
     pub fn get_msg_strings(&self) -> Vec<String> {
         self.messages.iter().map(|v| v.1.clone()).collect()
     }
+
+    /// renders the report as a text snippet with carets under each message span
+    pub fn render(&self, map: &SourceMap) -> String {
+        let mut out = format!("{}: {}\n", self.typ.display_str(), self.title);
+
+        let gutter = self
+            .messages
+            .iter()
+            .map(|(span, _)| (map.line_idx(span.start) + 1).to_string().len())
+            .max()
+            .unwrap_or(1);
+
+        for (span, msg) in self.messages.iter() {
+            let start = map.line_col(span.start);
+            let line_idx = map.line_idx(span.start);
+            let line = map.line_str(line_idx);
+
+            // multi-line spans only get underlined up to the end of their first line
+            let line_end = map.line_start(line_idx) + line.len();
+            let caret_start = map.char_col(span.start);
+            let caret_end = map.char_col(span.end.min(line_end).max(span.start));
+
+            out += &format!("{:gutter$}--> {}:{}\n", "", start.line, start.col);
+            out += &format!("{:gutter$} |\n", "");
+            out += &format!("{:>gutter$} | {}\n", start.line, line);
+            out += &format!(
+                "{:gutter$} | {}{} {}\n",
+                "",
+                " ".repeat(caret_start),
+                "^".repeat((caret_end - caret_start).max(1)),
+                msg,
+            );
+        }
+        out
+    }
 }
@@ -0,0 +1,229 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use lasso::Rodeo;
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    error::{make_error, Report},
+    execute,
+    source_map::SourceMap,
+    span::Span,
+    vm::{error::RuntimeError, value::Value, Arity, Vm},
+    RunResult,
+};
+
+make_error! {
+    @kind: Error;
+
+    InterpreterError {
+
+        @title: format!("Function from another interpreter");
+        @msgs: [];
+        ForeignFunction {}
+
+    }
+}
+
+static NEXT_ID: AtomicU32 = AtomicU32::new(0);
+static NEXT_HANDLE: AtomicU32 = AtomicU32::new(0);
+
+thread_local! {
+    /// the function and interpreter id behind each live `FunctionHandle`.
+    /// handles passed back inside a `JsValue` are looked up here by key, taking
+    /// them out through wasm-bindgen would free the caller's handle
+    static HANDLES: RefCell<HashMap<u32, (Value, u32)>> = RefCell::default();
+}
+
+/// a script function handed to JS, called through the `Interpreter::call` of
+/// the interpreter that returned it
+#[wasm_bindgen]
+pub struct FunctionHandle {
+    key: u32,
+}
+
+impl FunctionHandle {
+    fn new(value: Value, interpreter: u32) -> Self {
+        let key = NEXT_HANDLE.fetch_add(1, Ordering::Relaxed);
+        HANDLES.with_borrow_mut(|h| h.insert(key, (value, interpreter)));
+        Self { key }
+    }
+    /// the function and the id of the interpreter that created it
+    fn get(&self) -> (Value, u32) {
+        HANDLES.with_borrow(|h| h[&self.key].clone())
+    }
+}
+
+impl Drop for FunctionHandle {
+    fn drop(&mut self) {
+        HANDLES.with_borrow_mut(|h| h.remove(&self.key));
+    }
+}
+
+#[wasm_bindgen]
+impl FunctionHandle {
+    #[wasm_bindgen(js_name = toString)]
+    pub fn to_str(&self) -> String {
+        self.get().0.to_str()
+    }
+    /// identifies the handle when it's passed back inside an argument
+    #[wasm_bindgen(getter)]
+    pub fn key(&self) -> u32 {
+        self.key
+    }
+}
+
+/// keeps a vm alive so functions returned by a script can be called without
+/// rerunning it
+#[wasm_bindgen]
+pub struct Interpreter {
+    /// matched against `FunctionHandle::interpreter`, names are interned in
+    /// the rodeo of the interpreter that created the function
+    id: u32,
+    vm: Vm,
+    rodeo: Rodeo,
+    /// the last script run, errors are rendered against it
+    map: SourceMap,
+}
+
+impl Interpreter {
+    pub fn execute(&mut self, src: String) -> (Option<Value>, Vec<Report>) {
+        let result = execute(&src, &mut self.vm, &mut self.rodeo);
+        self.map = SourceMap::new(src);
+        result
+    }
+    pub fn call_value(
+        &mut self,
+        callee: Value,
+        args: Vec<Value>,
+        span: Span,
+    ) -> Result<Value, RuntimeError> {
+        self.vm.call_root(callee, args, span, &mut self.rodeo)
+    }
+    pub fn render(&self, report: &Report) -> String {
+        report.render(&self.map)
+    }
+    fn to_js(&self, value: &Value) -> JsValue {
+        to_js(value, self.id)
+    }
+}
+
+/// converts a value passed to JS, functions become `FunctionHandle`s
+fn to_js(value: &Value, interpreter: u32) -> JsValue {
+    value.to_js_with(&|f| FunctionHandle::new(f.clone(), interpreter).into())
+}
+
+/// converts a value passed in from JS, `FunctionHandle`s become the functions
+/// they stand for if they came from this interpreter
+fn from_js(value: &JsValue, interpreter: u32, span: Span) -> Result<Value, RuntimeError> {
+    Value::from_js(value, span, &|v| {
+        let obj = v.dyn_ref::<js_sys::Object>()?;
+        if obj.constructor().name() != "FunctionHandle" {
+            return None;
+        }
+        let handle = js_sys::Reflect::get(obj, &"key".into())
+            .ok()
+            .and_then(|key| key.as_f64())
+            .and_then(|key| HANDLES.with_borrow(|h| h.get(&(key as u32)).cloned()));
+        Some(match handle {
+            Some((value, id)) if id == interpreter => Ok(value),
+            Some(_) => Err(RuntimeError::ForeignFunction { span }),
+            // freed in JS already
+            None => Err(RuntimeError::CannotConvertJs {
+                typ: "FunctionHandle".into(),
+                span,
+            }),
+        })
+    })
+}
+
+/// the message of something thrown by JS
+fn js_error_message(err: &JsValue) -> String {
+    match err.dyn_ref::<js_sys::Error>() {
+        Some(err) => err.message().into(),
+        None => err.as_string().unwrap_or_else(|| "unknown error".into()),
+    }
+}
+
+#[wasm_bindgen]
+impl Interpreter {
+    /// `seed` picks the sequence the random builtins return
+    #[wasm_bindgen(constructor)]
+    pub fn new(seed: u32) -> Self {
+        let mut vm = Vm::new();
+        vm.set_seed(seed as u64);
+        Self {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            vm,
+            rodeo: Rodeo::new(),
+            map: SourceMap::new(String::new()),
+        }
+    }
+    /// makes `func` callable as `name` from scripts run after this. `max_args`
+    /// of `undefined` accepts any amount of extra arguments, and anything
+    /// thrown is reported as an error at the call
+    pub fn register_builtin(
+        &mut self,
+        name: String,
+        func: js_sys::Function,
+        min_args: usize,
+        max_args: Option<usize>,
+    ) {
+        let id = self.id;
+        let params = Arity {
+            min: min_args,
+            max: max_args,
+        };
+        self.vm
+            .register_builtin(&name, params, move |_, args, span, _| {
+                let js_args: js_sys::Array = args.iter().map(|a| to_js(a, id)).collect();
+                let out = func.apply(&JsValue::NULL, &js_args).map_err(|err| {
+                    RuntimeError::HostError {
+                        msg: js_error_message(&err),
+                        span,
+                    }
+                })?;
+                Ok(from_js(&out, id, span)?)
+            });
+    }
+    /// runs a script, functions in its result come back as `FunctionHandle`s
+    pub fn run(&mut self, src: String) -> RunResult {
+        let (value, reports) = self.execute(src);
+        RunResult {
+            value: value.map(|v| self.to_js(&v)),
+            reports,
+        }
+    }
+    /// calls a function returned by `run`, converting `args` from JS
+    pub fn call(&mut self, function: &FunctionHandle, args: Vec<JsValue>) -> RunResult {
+        let (function, interpreter) = function.get();
+        if interpreter != self.id {
+            return RunResult {
+                value: None,
+                reports: vec![InterpreterError::ForeignFunction {}.into_report()],
+            };
+        }
+        let span = match &function {
+            Value::Function(f) => f.body.span,
+            _ => Span::new(0, 0),
+        };
+        let result = args
+            .iter()
+            .map(|a| from_js(a, self.id, span))
+            .collect::<Result<_, _>>()
+            .and_then(|args| self.call_value(function, args, span));
+        match result {
+            Ok(v) => RunResult {
+                value: Some(self.to_js(&v)),
+                reports: vec![],
+            },
+            Err(e) => RunResult {
+                value: None,
+                reports: vec![e.into_report()],
+            },
+        }
+    }
+}
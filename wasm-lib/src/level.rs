@@ -0,0 +1,298 @@
+use std::{cell::RefCell, collections::BTreeMap, rc::Rc};
+
+use js_sys::{Array, Object, Reflect};
+use lasso::Rodeo;
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    error::make_error,
+    interpreter::Interpreter,
+    parser::{
+        ast::{Expr, Stmt},
+        Parser,
+    },
+    span::{Span, Spanned},
+    vm::value::{Value, ValueType},
+    RunResult,
+};
+
+make_error! {
+    @kind: Error;
+
+    LevelError {
+
+        @title: format!("Missing level field");
+        @msgs: [
+            span => "The level needs a `{}` field": field;
+        ];
+        MissingField {
+            field: String,
+            span: Span,
+        }
+
+        @title: format!("Invalid level field");
+        @msgs: [
+            span => "`{}` should be {}, found {}": field, expected.name(), found.name();
+        ];
+        InvalidField {
+            field: String,
+            expected: ValueType,
+            found: ValueType,
+            span: Span,
+        }
+
+        @title: format!("Invalid side count");
+        @msgs: [
+            span => "Patterns must be keyed by a side count, found `{}`": key;
+        ];
+        InvalidSides {
+            key: String,
+            span: Span,
+        }
+
+    }
+}
+
+/// where a value was written, found by following dict and array literals from
+/// the script's final expression. values built some other way point at the
+/// closest literal containing them
+#[derive(Clone, Copy)]
+struct Origin<'a> {
+    expr: Option<&'a Expr>,
+    span: Span,
+}
+impl<'a> Origin<'a> {
+    fn new(expr: &'a Spanned<Expr>) -> Self {
+        Self {
+            expr: Some(&expr.val),
+            span: expr.span,
+        }
+    }
+    fn unknown(self) -> Self {
+        Self {
+            expr: None,
+            span: self.span,
+        }
+    }
+    fn entry(self, key: &str) -> Option<&'a (Spanned<Rc<str>>, Spanned<Expr>)> {
+        match self.expr {
+            // the last of duplicate keys wins, like when the dict is built
+            Some(Expr::Dict(entries)) => entries.iter().rev().find(|(k, _)| &*k.val == key),
+            _ => None,
+        }
+    }
+    fn key(self, key: &str) -> Self {
+        self.entry(key)
+            .map_or_else(|| self.unknown(), |(_, v)| Self::new(v))
+    }
+    /// the key of an entry rather than its value
+    fn key_span(self, key: &str) -> Span {
+        self.entry(key).map_or(self.span, |(k, _)| k.span)
+    }
+    fn index(self, idx: usize) -> Self {
+        match self.expr {
+            Some(Expr::Array(elems)) => elems.get(idx).map_or_else(|| self.unknown(), Self::new),
+            _ => self.unknown(),
+        }
+    }
+}
+
+/// looks up `key` in a dict found at `path`, also returning the full field
+/// name for errors and where the field was written
+fn get<'a, 'b>(
+    dict: &'a BTreeMap<Rc<str>, Value>,
+    path: &str,
+    key: &str,
+    origin: Origin<'b>,
+) -> Result<(&'a Value, String, Origin<'b>), LevelError> {
+    let field = if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", path, key)
+    };
+    match dict.get(key) {
+        Some(v) => Ok((v, field, origin.key(key))),
+        None => Err(LevelError::MissingField {
+            field,
+            span: origin.span,
+        }),
+    }
+}
+
+fn expect_type(v: &Value, field: String, expected: ValueType, origin: Origin) -> LevelError {
+    LevelError::InvalidField {
+        field,
+        expected,
+        found: v.get_type(),
+        span: origin.span,
+    }
+}
+
+fn number(v: &Value, field: String, origin: Origin) -> Result<f64, LevelError> {
+    match v {
+        Value::Number(n) => Ok(*n),
+        v => Err(expect_type(v, field, ValueType::Number, origin)),
+    }
+}
+
+fn set(obj: &Object, key: &str, value: &JsValue) {
+    Reflect::set(obj, &JsValue::from_str(key), value).unwrap();
+}
+
+/// wraps a pattern function so the game can call it like a `Pattern`, errors
+/// are thrown as rendered reports
+fn pattern_fn(interp: &Rc<RefCell<Interpreter>>, pattern: Value) -> JsValue {
+    let interp = interp.clone();
+    let span = match &pattern {
+        Value::Function(f) => f.body.span,
+        _ => unreachable!(),
+    };
+    Closure::<dyn FnMut() -> Result<JsValue, JsValue>>::new(move || {
+        let mut interp = interp.borrow_mut();
+        interp
+            .call_value(pattern.clone(), vec![], span)
+            .and_then(|v| v.to_js_walls(span))
+            .map_err(|e| js_sys::Error::new(&interp.render(&e.into_report())).into())
+    })
+    .into_js_value()
+}
+
+/// a checked level script result, converted to a `LevelData` by `to_js`
+struct Level {
+    song: Rc<str>,
+    song_start_times: Vec<f64>,
+    bpm: f64,
+    beat_size: f64,
+    /// pattern functions and their weights, keyed by side count
+    patterns: Vec<(u32, Vec<(Value, f64)>)>,
+}
+
+impl Level {
+    fn from_value(value: &Value, origin: Origin) -> Result<Self, LevelError> {
+        let Value::Dict(level) = value else {
+            return Err(expect_type(value, "level".into(), ValueType::Dict, origin));
+        };
+
+        let song = match get(level, "", "song", origin)? {
+            (Value::String(s), _, _) => (&**s).into(),
+            (v, field, origin) => return Err(expect_type(v, field, ValueType::String, origin)),
+        };
+        let song_start_times = match get(level, "", "song_start_times", origin)? {
+            (Value::Array(arr), field, origin) => arr
+                .iter()
+                .enumerate()
+                .map(|(i, v)| number(v, format!("{}[{}]", field, i), origin.index(i)))
+                .collect::<Result<_, _>>()?,
+            (v, field, origin) => return Err(expect_type(v, field, ValueType::Array, origin)),
+        };
+        let (bpm, field, bpm_origin) = get(level, "", "bpm", origin)?;
+        let bpm = number(bpm, field, bpm_origin)?;
+        let (beat_size, field, beat_size_origin) = get(level, "", "beat_size", origin)?;
+        let beat_size = number(beat_size, field, beat_size_origin)?;
+
+        let (patterns_dict, patterns_origin) = match get(level, "", "patterns", origin)? {
+            (Value::Dict(d), _, origin) => (d, origin),
+            (v, field, origin) => return Err(expect_type(v, field, ValueType::Dict, origin)),
+        };
+        let mut patterns = vec![];
+        for (key, list) in patterns_dict.iter() {
+            let sides = key.parse::<u32>().ok().filter(|n| *n > 0).ok_or_else(|| {
+                LevelError::InvalidSides {
+                    key: key.to_string(),
+                    span: patterns_origin.key_span(key),
+                }
+            })?;
+            let path = format!("patterns.{}", key);
+            let list_origin = patterns_origin.key(key);
+            let Value::Array(list) = list else {
+                return Err(expect_type(list, path, ValueType::Array, list_origin));
+            };
+
+            let mut entries = vec![];
+            for (i, entry) in list.iter().enumerate() {
+                let path = format!("{}[{}]", path, i);
+                let entry_origin = list_origin.index(i);
+                let Value::Dict(entry) = entry else {
+                    return Err(expect_type(entry, path, ValueType::Dict, entry_origin));
+                };
+                let pattern = match get(entry, &path, "pattern", entry_origin)? {
+                    (f @ Value::Function(_), _, _) => f.clone(),
+                    (v, field, origin) => {
+                        return Err(expect_type(v, field, ValueType::Function, origin))
+                    }
+                };
+                let (weight, field, weight_origin) = get(entry, &path, "weight", entry_origin)?;
+                entries.push((pattern, number(weight, field, weight_origin)?));
+            }
+            patterns.push((sides, entries));
+        }
+
+        Ok(Self {
+            song,
+            song_start_times,
+            bpm,
+            beat_size,
+            patterns,
+        })
+    }
+
+    fn to_js(&self, interp: &Rc<RefCell<Interpreter>>) -> JsValue {
+        let patterns = Object::new();
+        for (sides, entries) in &self.patterns {
+            let list = Array::new();
+            for (pattern, weight) in entries {
+                let entry = Object::new();
+                set(&entry, "pattern", &pattern_fn(interp, pattern.clone()));
+                set(&entry, "weight", &(*weight).into());
+                list.push(&entry);
+            }
+            set(&patterns, &sides.to_string(), &list);
+        }
+
+        let obj = Object::new();
+        set(&obj, "song", &JsValue::from_str(&self.song));
+        let start_times: Array = self
+            .song_start_times
+            .iter()
+            .map(|t| JsValue::from(*t))
+            .collect();
+        set(&obj, "songStartTimes", &start_times);
+        set(&obj, "patterns", &patterns);
+        set(&obj, "bpm", &self.bpm.into());
+        set(&obj, "beatSize", &self.beat_size.into());
+        obj.into()
+    }
+}
+
+/// runs a level script, the result is a `LevelData` whose patterns call back
+/// into the script
+#[wasm_bindgen]
+pub fn load_level(src: String, seed: u32) -> RunResult {
+    // values don't remember where they were written, so the script is parsed
+    // again to point errors at the fields of its final expression
+    let mut rodeo = Rodeo::new();
+    let (ast, _) = Parser::new(&src, &mut rodeo).parse_root_recovering();
+    let origin = match &ast.val.ret {
+        Some(Spanned {
+            val: Stmt::Expr(expr),
+            ..
+        }) => Origin::new(expr),
+        None => Origin {
+            expr: None,
+            span: ast.span,
+        },
+    };
+
+    let mut interp = Interpreter::new(seed);
+    let (value, mut reports) = interp.execute(src);
+
+    let interp = Rc::new(RefCell::new(interp));
+    let value = value.and_then(|v| match Level::from_value(&v, origin) {
+        Ok(level) => Some(level.to_js(&interp)),
+        Err(e) => {
+            reports.push(e.into_report());
+            None
+        }
+    });
+    RunResult { value, reports }
+}
@@ -1,17 +1,19 @@
 #![deny(unused_must_use)]
 
 mod error;
+mod interpreter;
+mod level;
 mod parser;
+mod source_map;
 mod span;
 mod util;
 mod vm;
 
+use error::Report;
 use lasso::Rodeo;
-use parser::{
-    lexer::{Lexer, Token},
-    Parser,
-};
-use vm::Vm;
+use parser::{DocComment, Parser};
+use span::Span;
+use vm::{value::Value, Vm};
 use wasm_bindgen::prelude::*;
 
 #[wasm_bindgen(start)]
@@ -19,23 +21,85 @@ pub fn main() {
     std::panic::set_hook(Box::new(console_error_panic_hook::hook));
 }
 
-#[wasm_bindgen]
-pub fn bluh(s: String) {
-    let mut rodeo = Rodeo::new();
-    let mut parser = Parser::new(&s, &mut rodeo);
-    let ast = match parser.parse_block(true) {
-        Ok(ast) => ast,
+/// parses and runs `src`, returning the value of the root block if it ran
+/// without errors, along with the errors and warnings reported
+pub fn execute(src: &str, vm: &mut Vm, rodeo: &mut Rodeo) -> (Option<Value>, Vec<Report>) {
+    let mut parser = Parser::new(src, rodeo);
+    let (ast, errors) = parser.parse_root_recovering();
+    let mut reports: Vec<Report> = parser
+        .warnings()
+        .iter()
+        .map(|w| w.clone().into_report())
+        .collect();
+    if !errors.is_empty() {
+        reports.extend(errors.into_iter().map(|e| e.into_report()));
+        return (None, reports);
+    }
+
+    match vm.run_root(&ast, rodeo) {
+        Ok(v) => (Some(v), reports),
         Err(e) => {
-            console_log!("{:#?}", e);
-            return;
+            reports.push(e.into_report());
+            (None, reports)
         }
-    };
+    }
+}
+
+#[wasm_bindgen]
+pub struct RunResult {
+    value: Option<JsValue>,
+    reports: Vec<Report>,
+}
+
+#[wasm_bindgen]
+impl RunResult {
+    pub fn is_ok(&self) -> bool {
+        self.value.is_some()
+    }
+    pub fn get_value(&self) -> JsValue {
+        self.value.clone().unwrap_or(JsValue::UNDEFINED)
+    }
+    /// errors and warnings, see `Report::get_typ`
+    pub fn get_reports(&self) -> Vec<Report> {
+        self.reports.clone()
+    }
+}
 
+/// the doc comments in `src` and the declarations they document, scripts with
+/// errors still give the ones before the errors
+#[wasm_bindgen]
+pub fn get_docs(src: String) -> Vec<DocComment> {
+    let mut rodeo = Rodeo::new();
+    let mut parser = Parser::new(&src, &mut rodeo);
+    parser.parse_root_recovering();
+    parser.docs().to_vec()
+}
+
+/// runs a pattern script, its result is converted to a `PatternWall[]`
+#[wasm_bindgen]
+pub fn run_pattern(src: String, seed: u32) -> RunResult {
     let mut vm = Vm::new();
-    match vm.run_block(&ast, true, &mut rodeo) {
-        Ok(_) => {}
-        Err(e) => {
-            console_log!("{:#?}", e);
-        }
-    };
+    vm.set_seed(seed as u64);
+    let mut rodeo = Rodeo::new();
+    let (value, mut reports) = execute(&src, &mut vm, &mut rodeo);
+    let value = value.and_then(|v| {
+        let span = Span::new(0, src.len());
+        v.to_js_walls(span)
+            .map_err(|e| reports.push(e.into_report()))
+            .ok()
+    });
+    RunResult { value, reports }
+}
+
+/// runs a script, `seed` picks the sequence the random builtins return
+#[wasm_bindgen]
+pub fn run(src: String, seed: u32) -> RunResult {
+    let mut vm = Vm::new();
+    vm.set_seed(seed as u64);
+    let mut rodeo = Rodeo::new();
+    let (value, reports) = execute(&src, &mut vm, &mut rodeo);
+    RunResult {
+        value: value.map(|v| v.to_js()),
+        reports,
+    }
 }
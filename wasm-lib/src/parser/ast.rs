@@ -5,6 +5,7 @@ use lasso::{Rodeo, Spur};
 use crate::{
     span::{Span, Spannable, Spanned},
     util::BoxPostfix,
+    vm::value::{Str, ValueType},
 };
 
 use super::{
@@ -15,8 +16,10 @@ use super::{
 #[derive(Debug, Clone, PartialEq)]
 pub enum Expr {
     Number(f64),
-    String(Rc<str>),
+    String(Str),
     Bool(bool),
+    /// string literal with `{}` expressions, text parts are `Expr::String`s
+    Interpolated(Vec<Spanned<Expr>>),
 
     Ident(Spur),
 
@@ -26,17 +29,30 @@ pub enum Expr {
     Block(Box<Spanned<Block>>),
 
     Array(Vec<Spanned<Expr>>),
+    /// `a..b`, either end can be left out
+    Range {
+        start: Option<Box<Spanned<Expr>>>,
+        end: Option<Box<Spanned<Expr>>>,
+    },
+    Dict(Vec<(Spanned<Rc<str>>, Spanned<Expr>)>),
 
     Index {
         base: Box<Spanned<Expr>>,
         index: Box<Spanned<Expr>>,
     },
+    Member {
+        base: Box<Spanned<Expr>>,
+        name: Spanned<Spur>,
+    },
     Call {
         base: Box<Spanned<Expr>>,
         args: Vec<Spanned<Expr>>,
     },
 
-    Declaration(Spur, Box<Spanned<Expr>>),
+    Declaration {
+        pattern: Spanned<DeclPattern>,
+        value: Box<Spanned<Expr>>,
+    },
     Assign {
         op: AssignOp,
         pattern: Box<Spanned<PlacePattern>>,
@@ -60,11 +76,35 @@ pub enum Expr {
         step: Box<Spanned<Expr>>,
         body: Box<Spanned<Expr>>,
     },
+    /// `for x in iter` or `for i, x in iter`
+    ForIn {
+        index: Option<Spanned<Spur>>,
+        var: Spanned<Spur>,
+        iter: Box<Spanned<Expr>>,
+        body: Box<Spanned<Expr>>,
+    },
+
+    Match {
+        value: Box<Spanned<Expr>>,
+        arms: Vec<MatchArm>,
+    },
+
+    Return(Option<Box<Spanned<Expr>>>),
+    Break(Option<Box<Spanned<Expr>>>),
+    Continue,
 
     Function {
-        params: Vec<Spanned<Spur>>,
+        /// names with their default values
+        params: Vec<(Spanned<Spur>, Option<Spanned<Expr>>)>,
+        /// `...name`, collects the remaining arguments into an array
+        rest: Option<Spanned<Spur>>,
         body: Box<Spanned<Expr>>,
     },
+    /// `...value`, only parsed as a call argument
+    Spread(Box<Spanned<Expr>>),
+
+    /// placeholder for a statement that failed to parse
+    Error,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -78,13 +118,79 @@ pub struct Block {
     pub ret: Option<Spanned<Stmt>>,
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchArm {
+    pub pattern: Spanned<MatchPattern>,
+    pub guard: Option<Spanned<Expr>>,
+    pub body: Spanned<Expr>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MatchPattern {
+    /// `_`
+    Wildcard,
+    /// a plain name, matches anything and binds it for the guard and body
+    Bind(Spur),
+    Number(f64),
+    String(Rc<str>),
+    Bool(bool),
+    /// `$number` etc
+    Type(ValueType),
+    /// matches arrays of the same length whose elements all match
+    Array(Vec<Spanned<MatchPattern>>),
+}
+impl MatchPattern {
+    /// whether every value `other` matches is also matched by `self`
+    pub fn covers(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Wildcard | Self::Bind(_), _) => true,
+            (Self::Type(t), Self::Type(t2)) => t == t2,
+            (Self::Type(t), Self::Number(_)) => *t == ValueType::Number,
+            (Self::Type(t), Self::String(_)) => *t == ValueType::String,
+            (Self::Type(t), Self::Bool(_)) => *t == ValueType::Bool,
+            (Self::Type(t), Self::Array(_)) => *t == ValueType::Array,
+            (Self::Array(a), Self::Array(b)) => {
+                a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.val.covers(&b.val))
+            }
+            (a, b) => a == b,
+        }
+    }
+}
+
+/// what a `var` declaration binds
+#[derive(Debug, Clone, PartialEq)]
+pub enum DeclPattern {
+    Var(Spur),
+    /// `[a, b]`
+    Array(Vec<Spanned<DeclPattern>>),
+    /// `{a, b}`, binds the dict fields of the same names
+    Dict(Vec<Spanned<Spur>>),
+}
+
+impl DeclPattern {
+    /// the variables the pattern declares, in order
+    pub fn names(&self, out: &mut Vec<Spur>) {
+        match self {
+            DeclPattern::Var(name) => out.push(*name),
+            DeclPattern::Array(elems) => elems.iter().for_each(|e| e.names(out)),
+            DeclPattern::Dict(fields) => out.extend(fields.iter().map(|f| f.val)),
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum PlacePattern {
     Var(Spur),
+    /// `[a, b] = ...`, only valid for plain `=` and not as the base of an index
+    Array(Vec<Spanned<PlacePattern>>),
     Index {
         base: Box<Spanned<PlacePattern>>,
         index: Spanned<Expr>,
     },
+    Member {
+        base: Box<Spanned<PlacePattern>>,
+        name: Spanned<Spur>,
+    },
 }
 impl PlacePattern {
     pub fn from_expr(expr: Spanned<Expr>, rodeo: &Rodeo) -> Result<Self, ParserError> {
@@ -97,14 +203,31 @@ impl PlacePattern {
                 }
                 Self::Var(s)
             }
+            Expr::Array(elems) => Self::Array(
+                elems
+                    .into_iter()
+                    .map(|e| {
+                        let span = e.span;
+                        Ok(Self::from_expr(e, rodeo)?.spanned(span))
+                    })
+                    .collect::<Result<_, _>>()?,
+            ),
             Expr::Index { base, index } => Self::Index {
-                base: {
-                    let span = base.span;
-                    Self::from_expr(*base, rodeo)?.spanned(span).boxed()
-                },
+                base: Self::from_base(*base, rodeo)?,
                 index: *index,
             },
+            Expr::Member { base, name } => Self::Member {
+                base: Self::from_base(*base, rodeo)?,
+                name,
+            },
             _ => return Err(ParserError::InvalidAssignExpression { span: expr.span }),
         })
     }
+    fn from_base(base: Spanned<Expr>, rodeo: &Rodeo) -> Result<Box<Spanned<Self>>, ParserError> {
+        let span = base.span;
+        match Self::from_expr(base, rodeo)? {
+            Self::Array(_) => Err(ParserError::InvalidAssignExpression { span }),
+            p => Ok(p.spanned(span).boxed()),
+        }
+    }
 }
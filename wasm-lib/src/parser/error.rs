@@ -33,6 +33,57 @@ make_error! {
             span: Span,
         }
 
+        @title: format!("Dangling doc comment");
+        @msgs: [
+            span => "Doc comments must be followed by a `var` declaration";
+        ];
+        DanglingDocComment {
+            span: Span,
+        }
+
+        @title: format!("Invalid escape sequence");
+        @msgs: [
+            span => "`{}` is not a valid escape sequence": escape;
+        ];
+        InvalidEscape {
+            escape: String,
+            span: Span,
+        }
+
+        @title: format!("Invalid number literal");
+        @msgs: [
+            span => "`{}` is not a valid number": literal;
+        ];
+        InvalidNumber {
+            literal: String,
+            span: Span,
+        }
+
+        @title: format!("Return outside of function");
+        @msgs: [
+            span => "`return` can only be used inside a function";
+        ];
+        ReturnOutsideFunction {
+            span: Span,
+        }
+
+        @title: format!("`{}` outside of loop", keyword.name());
+        @msgs: [
+            span => "`{}` can only be used inside a loop": keyword.name();
+        ];
+        OutsideLoop {
+            keyword: Token,
+            span: Span,
+        }
+
+        @title: format!("Interpolated dict key");
+        @msgs: [
+            span => "Dict keys cannot contain interpolated expressions";
+        ];
+        InterpolatedKey {
+            span: Span,
+        }
+
         @title: format!("No matching parenthesis");
         @msgs: [
             span => "Cannot find a matching `)` for this `(`";
@@ -41,5 +92,48 @@ make_error! {
             span: Span,
         }
 
+        @title: format!("Parameter after rest parameter");
+        @msgs: [
+            span => "The rest parameter must be the last one";
+        ];
+        ParamAfterRest {
+            span: Span,
+        }
+
+        @title: format!("Unknown type");
+        @msgs: [
+            span => "There is no type called `{}`": name;
+        ];
+        UnknownType {
+            name: String,
+            span: Span,
+        }
+
+        @title: format!("Interpolated pattern");
+        @msgs: [
+            span => "Patterns cannot contain interpolated expressions";
+        ];
+        InterpolatedPattern {
+            span: Span,
+        }
+
+    }
+}
+
+make_error! {
+    @kind: Warning;
+
+    ParserWarning {
+
+        @title: format!("Unreachable match arm");
+        @msgs: [
+            span => "This arm can never be reached";
+            covered_by => "Because this earlier arm already matches its values";
+        ];
+        UnreachableArm {
+            span: Span,
+            covered_by: Span,
+        }
+
     }
 }
@@ -1,16 +1,26 @@
-use logos::Logos;
+use logos::{Logos, Skip};
 
 use crate::span::Span;
 
 #[derive(Logos, Debug, PartialEq, Eq, Clone, Copy)]
 #[logos(skip r"[ \t\r\n\f]+")] // Ignore this regex pattern between tokens
+#[logos(skip r"//([^/\n][^\n]*)?|////[^\n]*")] // line comments, but not doc comments
 pub enum Token {
     #[regex("[a-zA-Z_$][a-zA-Z_0-9]*")]
     Ident,
-    #[regex("[0-9]+(\\.[0-9]*)?")]
+    #[regex("[0-9][0-9_]*(\\.[0-9][0-9_]*)?([eE][+-]?[0-9_]*)?", trailing_dot)]
+    #[regex("\\.[0-9][0-9_]*([eE][+-]?[0-9_]*)?")]
+    #[regex("0[xXbB][0-9a-zA-Z_]*")]
     Number,
-    #[regex(r##""(?:[^"]|\\")*""##)]
+    #[token("\"", string)]
     String,
+    #[regex("r#*\"", raw_string)]
+    RawString,
+
+    #[regex("///([^/\n][^\n]*)?")]
+    DocComment,
+    #[token("/*", block_comment)]
+    BlockComment,
 
     #[token("+")]
     Plus,
@@ -25,6 +35,26 @@ pub enum Token {
     #[token("**")]
     Pow,
 
+    #[token("&&")]
+    And,
+    #[token("||")]
+    Or,
+    #[token("!")]
+    Not,
+
+    #[token("&")]
+    BitAnd,
+    #[token("|")]
+    BitOr,
+    #[token("^")]
+    BitXor,
+    #[token("~")]
+    BitNot,
+    #[token("<<")]
+    ShiftLeft,
+    #[token(">>")]
+    ShiftRight,
+
     #[token("=")]
     Assign,
     #[token("+=")]
@@ -39,6 +69,20 @@ pub enum Token {
     ModAssign,
     #[token("**=")]
     PowAssign,
+    #[token("&&=")]
+    AndAssign,
+    #[token("||=")]
+    OrAssign,
+    #[token("&=")]
+    BitAndAssign,
+    #[token("|=")]
+    BitOrAssign,
+    #[token("^=")]
+    BitXorAssign,
+    #[token("<<=")]
+    ShiftLeftAssign,
+    #[token(">>=")]
+    ShiftRightAssign,
 
     #[token("==")]
     Eq,
@@ -72,6 +116,12 @@ pub enum Token {
     Semicolon,
     #[token(":")]
     Colon,
+    #[token(".")]
+    Dot,
+    #[token("..")]
+    DotDot,
+    #[token("...")]
+    Ellipsis,
 
     #[token("=>")]
     FatArrow,
@@ -91,6 +141,16 @@ pub enum Token {
     While,
     #[token("for")]
     For,
+    #[token("in")]
+    In,
+    #[token("match")]
+    Match,
+    #[token("return")]
+    Return,
+    #[token("break")]
+    Break,
+    #[token("continue")]
+    Continue,
 
     #[token("dbg")]
     Dbg,
@@ -105,12 +165,24 @@ impl Token {
             Token::Ident => "identifier",
             Token::Number => "number",
             Token::String => "string literal",
+            Token::RawString => "raw string literal",
+            Token::DocComment => "doc comment",
+            Token::BlockComment => "block comment",
             Token::Plus => "+",
             Token::Minus => "-",
             Token::Mult => "*",
             Token::Div => "/",
             Token::Mod => "%",
             Token::Pow => "**",
+            Token::And => "&&",
+            Token::Or => "||",
+            Token::Not => "!",
+            Token::BitAnd => "&",
+            Token::BitOr => "|",
+            Token::BitXor => "^",
+            Token::BitNot => "~",
+            Token::ShiftLeft => "<<",
+            Token::ShiftRight => ">>",
             Token::Assign => "=",
             Token::Unknown => "unknown",
             Token::Eof => "end of file",
@@ -123,6 +195,9 @@ impl Token {
             Token::Comma => ",",
             Token::Semicolon => ";",
             Token::Colon => ":",
+            Token::Dot => ".",
+            Token::DotDot => "..",
+            Token::Ellipsis => "...",
             Token::Var => "var",
             Token::PlusAssign => "+=",
             Token::MinusAssign => "-=",
@@ -130,10 +205,22 @@ impl Token {
             Token::DivAssign => "/=",
             Token::ModAssign => "%=",
             Token::PowAssign => "**=",
+            Token::AndAssign => "&&=",
+            Token::OrAssign => "||=",
+            Token::BitAndAssign => "&=",
+            Token::BitOrAssign => "|=",
+            Token::BitXorAssign => "^=",
+            Token::ShiftLeftAssign => "<<=",
+            Token::ShiftRightAssign => ">>=",
             Token::If => "if",
             Token::Else => "else",
             Token::While => "while",
             Token::For => "for",
+            Token::In => "in",
+            Token::Match => "match",
+            Token::Return => "return",
+            Token::Break => "break",
+            Token::Continue => "continue",
             Token::Eq => "==",
             Token::NEq => "!=",
             Token::Lt => "<",
@@ -148,6 +235,114 @@ impl Token {
     }
 }
 
+/// length of the escape sequence starting at `s[0]`, `\u{...}` is skipped whole
+/// so its braces aren't taken for an interpolation. an unclosed one stops before
+/// the next quote and is reported by `unescape`
+pub fn escape_len(s: &[u8]) -> usize {
+    if !s[1..].starts_with(b"u{") {
+        return s.len().min(2);
+    }
+    match s[3..].iter().position(|c| matches!(c, b'}' | b'"')) {
+        Some(p) if s[3 + p] == b'}' => p + 4,
+        Some(p) => p + 3,
+        None => s.len(),
+    }
+}
+
+/// length of a string literal's contents including the closing quote, skipping over
+/// nested strings inside `{}` interpolations
+fn string_len(s: &[u8]) -> Option<usize> {
+    let mut depth = 0;
+    let mut i = 0;
+    while i < s.len() {
+        match s[i] {
+            b'\\' => {
+                i += escape_len(&s[i..]);
+                continue;
+            }
+            b'{' => depth += 1,
+            b'}' if depth > 0 => depth -= 1,
+            b'"' if depth == 0 => return Some(i + 1),
+            b'"' => i += string_len(&s[i + 1..])?,
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+fn string(lexer: &mut logos::Lexer<Token>) -> Result<(), ()> {
+    match string_len(lexer.remainder().as_bytes()) {
+        Some(len) => {
+            lexer.bump(len);
+            Ok(())
+        }
+        None => {
+            lexer.bump(lexer.remainder().len());
+            Err(())
+        }
+    }
+}
+
+/// takes the `.` of numbers like `1.`, unless it starts a range or a member
+fn trailing_dot(lexer: &mut logos::Lexer<Token>) {
+    if !lexer
+        .slice()
+        .bytes()
+        .all(|c| c.is_ascii_digit() || c == b'_')
+    {
+        return;
+    }
+    let rem = lexer.remainder().as_bytes();
+    if rem.first() == Some(&b'.')
+        && !rem
+            .get(1)
+            .is_some_and(|&c| c == b'.' || c == b'_' || c == b'$' || c.is_ascii_alphabetic())
+    {
+        lexer.bump(1);
+    }
+}
+
+/// finds the closing quote of a raw string, matching the amount of `#`s it was opened with
+fn raw_string(lexer: &mut logos::Lexer<Token>) -> Result<(), ()> {
+    let hashes = lexer.slice().len() - 2;
+    let end = format!("\"{}", "#".repeat(hashes));
+    match lexer.remainder().find(&end) {
+        Some(i) => {
+            lexer.bump(i + end.len());
+            Ok(())
+        }
+        None => {
+            lexer.bump(lexer.remainder().len());
+            Err(())
+        }
+    }
+}
+
+/// skips a (possibly nested) block comment, erroring if it is never closed
+fn block_comment(lexer: &mut logos::Lexer<Token>) -> Result<Skip, ()> {
+    let rem = lexer.remainder().as_bytes();
+    let mut depth = 1;
+    let mut i = 0;
+    while i < rem.len() {
+        if rem[i..].starts_with(b"/*") {
+            depth += 1;
+            i += 2;
+        } else if rem[i..].starts_with(b"*/") {
+            depth -= 1;
+            i += 2;
+            if depth == 0 {
+                lexer.bump(i);
+                return Ok(Skip);
+            }
+        } else {
+            i += 1;
+        }
+    }
+    lexer.bump(rem.len());
+    Err(())
+}
+
 fn inner_next<'a>(lexer: &mut logos::Lexer<'a, Token>) -> Token {
     lexer
         .next()
@@ -167,6 +362,18 @@ impl<'a> Lexer<'a> {
             peeked: None,
         }
     }
+    /// creates a lexer that starts at byte `offset` of `src`
+    pub fn new_at(src: &'a str, offset: usize) -> Self {
+        let mut inner = Token::lexer(src);
+        inner.bump(offset);
+        Self {
+            inner,
+            peeked: None,
+        }
+    }
+    pub fn source(&self) -> &'a str {
+        self.inner.source()
+    }
     pub fn peek(&mut self) -> Token {
         if let Some(p) = self.peeked {
             return p;
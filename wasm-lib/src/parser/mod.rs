@@ -5,20 +5,125 @@ pub mod operators;
 
 use std::{mem, rc::Rc};
 
-use ast::{Block, Expr, PlacePattern, Stmt};
-use error::ParserError;
+use ast::{Block, DeclPattern, Expr, MatchArm, MatchPattern, PlacePattern, Stmt};
+use error::{ParserError, ParserWarning};
 use lasso::{Rodeo, Spur};
-use lexer::{Lexer, Token};
+use lexer::{escape_len, Lexer, Token};
+use wasm_bindgen::prelude::*;
 
 use crate::{
     console_log,
     span::{Span, Spannable, Spanned},
     util::BoxPostfix,
+    vm::value::ValueType,
 };
 
+/// decodes the escape sequences in the contents of a string literal, `offset` being
+/// where the contents start in the source
+pub fn unescape(s: &str, offset: usize) -> Result<String, ParserError> {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.char_indices().peekable();
+
+    while let Some((i, c)) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        let invalid = |end: usize| ParserError::InvalidEscape {
+            escape: s[i..end].into(),
+            span: Span::new(offset + i, offset + end),
+        };
+        let Some((j, e)) = chars.next() else {
+            return Err(invalid(s.len()));
+        };
+        out.push(match e {
+            'n' => '\n',
+            't' => '\t',
+            'r' => '\r',
+            '0' => '\0',
+            '\\' => '\\',
+            '"' => '"',
+            '{' => '{',
+            '}' => '}',
+            '\'' => '\'',
+            'u' => {
+                if chars.next_if(|(_, c)| *c == '{').is_none() {
+                    return Err(invalid(j + 1));
+                }
+                let mut end = j + 2;
+                let mut closed = false;
+                for (k, c) in chars.by_ref() {
+                    end = k + c.len_utf8();
+                    if c == '}' {
+                        closed = true;
+                        break;
+                    }
+                }
+                let hex = &s[j + 2..end - closed as usize];
+                match u32::from_str_radix(hex, 16).ok().and_then(char::from_u32) {
+                    Some(c) if closed && hex.len() <= 6 => c,
+                    _ => return Err(invalid(end)),
+                }
+            }
+            _ => return Err(invalid(j + e.len_utf8())),
+        });
+    }
+    Ok(out)
+}
+
+/// parses decimal, hex (`0x`) and binary (`0b`) literals, ignoring `_` separators
+pub fn parse_number(s: &str) -> Option<f64> {
+    let s = s.replace('_', "");
+    let radix = |digits: &str, radix| {
+        u64::from_str_radix(digits, radix)
+            .ok()
+            .filter(|_| !digits.starts_with(['+', '-']))
+            .map(|v| v as f64)
+    };
+    match s.get(..2) {
+        Some("0x" | "0X") => radix(&s[2..], 16),
+        Some("0b" | "0B") => radix(&s[2..], 2),
+        _ => s.parse().ok(),
+    }
+}
+
+/// the contents of `///` comments and the declaration below them, for editor
+/// hovers
+#[wasm_bindgen]
+#[derive(Debug, Clone, PartialEq)]
+pub struct DocComment {
+    names: Vec<String>,
+    span: Span,
+    doc: String,
+}
+
+#[wasm_bindgen]
+impl DocComment {
+    /// the variables the declaration creates
+    pub fn get_names(&self) -> Vec<String> {
+        self.names.clone()
+    }
+    /// the declaration's pattern
+    pub fn get_span(&self) -> Span {
+        self.span
+    }
+    pub fn get_doc(&self) -> String {
+        self.doc.clone()
+    }
+}
+
 pub struct Parser<'a> {
     lexer: Lexer<'a>,
     rodeo: &'a mut Rodeo,
+    recovering: bool,
+    errors: Vec<ParserError>,
+    /// whether an error at the end of the source was reported, every block
+    /// left open would report another one there
+    reported_eof: bool,
+    warnings: Vec<ParserWarning>,
+    docs: Vec<DocComment>,
+    in_function: bool,
+    in_loop: bool,
 }
 
 impl<'a> Parser<'a> {
@@ -26,6 +131,13 @@ impl<'a> Parser<'a> {
         Self {
             lexer: Lexer::new(src),
             rodeo,
+            recovering: false,
+            errors: vec![],
+            reported_eof: false,
+            warnings: vec![],
+            docs: vec![],
+            in_function: false,
+            in_loop: false,
         }
     }
 
@@ -112,16 +224,25 @@ impl<'a> Parser<'a> {
         Ok(match self.peek_tok() {
             Token::Number => {
                 self.next_tok();
-                Expr::Number(self.slice().parse().unwrap()).spanned(self.span())
+                let n = parse_number(self.slice()).ok_or_else(|| ParserError::InvalidNumber {
+                    literal: self.slice().into(),
+                    span: self.span(),
+                })?;
+                Expr::Number(n).spanned(self.span())
             }
             Token::Ident => {
                 self.next_tok();
                 Expr::Ident(self.slice_intern()).spanned(self.span())
             }
             Token::String => {
+                self.next_tok();
+                self.parse_string()?
+            }
+            Token::RawString => {
                 self.next_tok();
                 let s = self.slice();
-                let s = s[1..s.len() - 1].replace("\\\"", "\"");
+                let hashes = s.find('"').unwrap();
+                let s = &s[hashes + 1..s.len() - hashes];
                 Expr::String(s.into()).spanned(self.span())
             }
             Token::True => {
@@ -164,18 +285,34 @@ impl<'a> Parser<'a> {
                     inner.val.spanned(start.extended(self.span()))
                 } else {
                     let mut params = vec![];
+                    let mut rest = None;
 
                     self.list_parse(Token::Comma, Token::CloseParen, |slef| {
-                        slef.expect_tok(Token::Ident)?;
-                        params.push(slef.slice_intern().spanned(slef.span()));
+                        if rest.is_some() {
+                            return Err(ParserError::ParamAfterRest {
+                                span: slef.peek_span(),
+                            });
+                        }
+                        if slef.skip_tok(Token::Ellipsis) {
+                            rest = Some(slef.parse_var_name("parameter name")?);
+                            return Ok(());
+                        }
+                        let name = slef.parse_var_name("parameter name")?;
+                        let default = if slef.skip_tok(Token::Assign) {
+                            Some(slef.parse_function_body()?)
+                        } else {
+                            None
+                        };
+                        params.push((name, default));
                         Ok(())
                     })?;
                     self.expect_tok(Token::FatArrow)?;
 
-                    let body = self.parse_expr()?;
+                    let body = self.parse_function_body()?;
 
                     Expr::Function {
                         params,
+                        rest,
                         body: body.boxed(),
                     }
                     .spanned(start.extended(self.span()))
@@ -194,6 +331,44 @@ impl<'a> Parser<'a> {
 
                 Expr::Array(v).spanned(start.extended(self.span()))
             }
+            Token::OpenCurly
+                if matches!(
+                    self.peek_toks(),
+                    [_, Token::CloseCurly, _] | [_, Token::Ident | Token::String, Token::Colon]
+                ) =>
+            {
+                self.next_tok();
+                let start = self.span();
+
+                let mut entries = vec![];
+
+                self.list_parse(Token::Comma, Token::CloseCurly, |slef| {
+                    let key = match slef.next_tok() {
+                        Token::Ident => slef.slice_rc().spanned(slef.span()),
+                        Token::String => match slef.parse_string()? {
+                            Spanned {
+                                val: Expr::String(s),
+                                span,
+                            } => Rc::<str>::from(&*s).spanned(span),
+                            Spanned { span, .. } => {
+                                return Err(ParserError::InterpolatedKey { span })
+                            }
+                        },
+                        t => {
+                            return Err(ParserError::Expected {
+                                expected: "dict key".into(),
+                                found: t,
+                                span: slef.span(),
+                            })
+                        }
+                    };
+                    slef.expect_tok(Token::Colon)?;
+                    entries.push((key, slef.parse_expr()?));
+                    Ok(())
+                })?;
+
+                Expr::Dict(entries).spanned(start.extended(self.span()))
+            }
             Token::OpenCurly => {
                 self.next_tok();
                 let start = self.span();
@@ -227,7 +402,7 @@ impl<'a> Parser<'a> {
                 self.next_tok();
                 let start = self.span();
                 let cond = self.parse_expr()?;
-                let body = self.parse_expr()?;
+                let body = self.parse_loop_body()?;
                 Expr::While {
                     cond: cond.boxed(),
                     body: body.boxed(),
@@ -237,12 +412,37 @@ impl<'a> Parser<'a> {
             Token::For => {
                 self.next_tok();
                 let start = self.span();
+
+                let for_in = matches!(self.peek_toks(), [Token::Ident, Token::In])
+                    || matches!(
+                        self.peek_toks(),
+                        [Token::Ident, Token::Comma, Token::Ident, Token::In]
+                    );
+                if for_in {
+                    let mut var = self.parse_var_name("loop variable")?;
+                    let mut index = None;
+                    if self.skip_tok(Token::Comma) {
+                        index = Some(var);
+                        var = self.parse_var_name("loop variable")?;
+                    }
+                    self.expect_tok(Token::In)?;
+                    let iter = self.parse_expr()?;
+                    let body = self.parse_loop_body()?;
+                    return Ok(Expr::ForIn {
+                        index,
+                        var,
+                        iter: iter.boxed(),
+                        body: body.boxed(),
+                    }
+                    .spanned(start.extended(self.span())));
+                }
+
                 let init = self.parse_expr()?;
                 self.expect_tok(Token::Comma)?;
                 let cond = self.parse_expr()?;
                 self.expect_tok(Token::Comma)?;
                 let step = self.parse_expr()?;
-                let body = self.parse_expr()?;
+                let body = self.parse_loop_body()?;
                 Expr::For {
                     init: init.boxed(),
                     cond: cond.boxed(),
@@ -251,19 +451,93 @@ impl<'a> Parser<'a> {
                 }
                 .spanned(start.extended(self.span()))
             }
-            Token::Var => {
+            Token::Match => {
                 self.next_tok();
                 let start = self.span();
-                self.expect_tok_named(Token::Ident, "variable name")?;
+                let value = self.parse_expr()?;
+                self.expect_tok(Token::OpenCurly)?;
+
+                let mut arms = vec![];
+                self.list_parse(Token::Comma, Token::CloseCurly, |slef| {
+                    let pattern = slef.parse_match_pattern()?;
+                    let guard = if slef.skip_tok(Token::If) {
+                        Some(slef.parse_expr()?)
+                    } else {
+                        None
+                    };
+                    slef.expect_tok(Token::FatArrow)?;
+                    let body = slef.parse_expr()?;
+                    arms.push(MatchArm {
+                        pattern,
+                        guard,
+                        body,
+                    });
+                    Ok(())
+                })?;
 
-                if self.slice().starts_with("$") {
-                    return Err(ParserError::UserDefinedSpecialIdent { span: self.span() });
+                for (i, arm) in arms.iter().enumerate() {
+                    let covering = arms[..i]
+                        .iter()
+                        .find(|a| a.guard.is_none() && a.pattern.val.covers(&arm.pattern.val));
+                    if let Some(covering) = covering {
+                        self.warnings.push(ParserWarning::UnreachableArm {
+                            span: arm.pattern.span,
+                            covered_by: covering.pattern.span,
+                        });
+                    }
                 }
-                let name = self.slice_intern();
 
-                self.expect_tok(Token::Assign)?;
-                let val = self.parse_expr()?;
-                Expr::Declaration(name, val.boxed()).spanned(start.extended(self.span()))
+                Expr::Match {
+                    value: value.boxed(),
+                    arms,
+                }
+                .spanned(start.extended(self.span()))
+            }
+            Token::Return => {
+                self.next_tok();
+                let start = self.span();
+                if !self.in_function {
+                    return Err(ParserError::ReturnOutsideFunction { span: start });
+                }
+                let v = self.parse_control_value()?;
+                Expr::Return(v.map(Box::new)).spanned(start.extended(self.span()))
+            }
+            Token::Break => {
+                self.next_tok();
+                let start = self.span();
+                if !self.in_loop {
+                    return Err(ParserError::OutsideLoop {
+                        keyword: Token::Break,
+                        span: start,
+                    });
+                }
+                let v = self.parse_control_value()?;
+                Expr::Break(v.map(Box::new)).spanned(start.extended(self.span()))
+            }
+            Token::Continue => {
+                self.next_tok();
+                if !self.in_loop {
+                    return Err(ParserError::OutsideLoop {
+                        keyword: Token::Continue,
+                        span: self.span(),
+                    });
+                }
+                Expr::Continue.spanned(self.span())
+            }
+            Token::Var => self.parse_declaration(None)?,
+            Token::DocComment => {
+                let start = self.peek_span();
+                let mut lines = vec![];
+                while self.skip_tok(Token::DocComment) {
+                    let line = &self.slice()[3..];
+                    lines.push(line.strip_prefix(' ').unwrap_or(line).to_string());
+                }
+                if !self.next_is(Token::Var) {
+                    return Err(ParserError::DanglingDocComment {
+                        span: start.extended(self.span()),
+                    });
+                }
+                self.parse_declaration(Some(lines.join("\n")))?
             }
             unary_op
                 if {
@@ -292,6 +566,185 @@ impl<'a> Parser<'a> {
             }
         })
     }
+    /// meant to be called after passing the string token, splits the literal
+    /// into its text parts and `{}` interpolated expressions
+    fn parse_string(&mut self) -> Result<Spanned<Expr>, ParserError> {
+        let span = self.span();
+        let src = self.lexer.source();
+        let content_end = span.end - 1;
+
+        let mut parts = vec![];
+        let mut lit_start = span.start + 1;
+        let mut i = lit_start;
+
+        while i < content_end {
+            match src.as_bytes()[i] {
+                b'\\' => i += escape_len(&src.as_bytes()[i..content_end]),
+                b'{' => {
+                    if lit_start < i {
+                        let lit = unescape(&src[lit_start..i], lit_start)?;
+                        parts.push(Expr::String(lit.into()).spanned(lit_start..i));
+                    }
+
+                    // the embedded expression is lexed from the string's contents
+                    // only, so a comment can't run past the closing quote
+                    let inner = Lexer::new_at(&src[..content_end], i + 1);
+                    let old_lexer = mem::replace(&mut self.lexer, inner);
+                    let expr = self
+                        .parse_expr()
+                        .and_then(|expr| self.expect_tok(Token::CloseCurly).map(|_| expr));
+                    i = self.span().end;
+                    self.lexer = old_lexer;
+
+                    parts.push(expr?);
+                    lit_start = i;
+                }
+                _ => i += 1,
+            }
+        }
+
+        let lit = unescape(&src[lit_start..content_end], lit_start)?;
+        if parts.is_empty() {
+            return Ok(Expr::String(lit.into()).spanned(span));
+        }
+        if !lit.is_empty() {
+            parts.push(Expr::String(lit.into()).spanned(lit_start..content_end));
+        }
+        Ok(Expr::Interpolated(parts).spanned(span))
+    }
+    /// the optional value after `return` or `break`
+    fn parse_control_value(&mut self) -> Result<Option<Spanned<Expr>>, ParserError> {
+        Ok(match self.peek_tok() {
+            Token::Semicolon
+            | Token::CloseCurly
+            | Token::CloseParen
+            | Token::CloseSquare
+            | Token::Comma
+            | Token::Else
+            | Token::Eof => None,
+            _ => Some(self.parse_expr()?),
+        })
+    }
+    /// `break` and `continue` are allowed inside
+    fn parse_loop_body(&mut self) -> Result<Spanned<Expr>, ParserError> {
+        let outer = mem::replace(&mut self.in_loop, true);
+        let body = self.parse_expr();
+        self.in_loop = outer;
+        body
+    }
+    /// `return` is allowed inside, but loops outside the function can't be broken out of.
+    /// also used for parameter defaults, which run inside the function
+    fn parse_function_body(&mut self) -> Result<Spanned<Expr>, ParserError> {
+        let outer_function = mem::replace(&mut self.in_function, true);
+        let outer_loop = mem::replace(&mut self.in_loop, false);
+        let body = self.parse_expr();
+        self.in_function = outer_function;
+        self.in_loop = outer_loop;
+        body
+    }
+    fn parse_match_pattern(&mut self) -> Result<Spanned<MatchPattern>, ParserError> {
+        let start = self.peek_span();
+        let pattern = match self.next_tok() {
+            Token::Ident => match self.slice() {
+                "_" => MatchPattern::Wildcard,
+                s if s.starts_with('$') => {
+                    let typ = ValueType::TYPES.iter().find(|t| t.name() == &s[1..]);
+                    MatchPattern::Type(*typ.ok_or_else(|| ParserError::UnknownType {
+                        name: s.into(),
+                        span: self.span(),
+                    })?)
+                }
+                _ => MatchPattern::Bind(self.slice_intern()),
+            },
+            Token::True => MatchPattern::Bool(true),
+            Token::False => MatchPattern::Bool(false),
+            t @ (Token::Number | Token::Minus) => {
+                if t == Token::Minus {
+                    self.expect_tok_named(Token::Number, "number")?;
+                }
+                let n = parse_number(self.slice()).ok_or_else(|| ParserError::InvalidNumber {
+                    literal: self.slice().into(),
+                    span: self.span(),
+                })?;
+                MatchPattern::Number(if t == Token::Minus { -n } else { n })
+            }
+            Token::String => match self.parse_string()?.val {
+                Expr::String(s) => MatchPattern::String((&*s).into()),
+                _ => return Err(ParserError::InterpolatedPattern { span: self.span() }),
+            },
+            Token::OpenSquare => {
+                let mut elems = vec![];
+                self.list_parse(Token::Comma, Token::CloseSquare, |slef| {
+                    elems.push(slef.parse_match_pattern()?);
+                    Ok(())
+                })?;
+                MatchPattern::Array(elems)
+            }
+            found => {
+                return Err(ParserError::Expected {
+                    expected: "pattern".into(),
+                    found,
+                    span: self.span(),
+                })
+            }
+        };
+        Ok(pattern.spanned(start.extended(self.span())))
+    }
+    /// parses a variable name that can be declared
+    fn parse_var_name(&mut self, name: &str) -> Result<Spanned<Spur>, ParserError> {
+        self.expect_tok_named(Token::Ident, name)?;
+        if self.slice().starts_with("$") {
+            return Err(ParserError::UserDefinedSpecialIdent { span: self.span() });
+        }
+        Ok(self.slice_intern().spanned(self.span()))
+    }
+    fn parse_decl_pattern(&mut self) -> Result<Spanned<DeclPattern>, ParserError> {
+        let start = self.peek_span();
+        let pattern = match self.peek_tok() {
+            Token::OpenSquare => {
+                self.next_tok();
+                let mut elems = vec![];
+                self.list_parse(Token::Comma, Token::CloseSquare, |slef| {
+                    elems.push(slef.parse_decl_pattern()?);
+                    Ok(())
+                })?;
+                DeclPattern::Array(elems)
+            }
+            Token::OpenCurly => {
+                self.next_tok();
+                let mut fields = vec![];
+                self.list_parse(Token::Comma, Token::CloseCurly, |slef| {
+                    fields.push(slef.parse_var_name("field name")?);
+                    Ok(())
+                })?;
+                DeclPattern::Dict(fields)
+            }
+            _ => DeclPattern::Var(self.parse_var_name("variable name")?.val),
+        };
+        Ok(pattern.spanned(start.extended(self.span())))
+    }
+    fn parse_declaration(&mut self, doc: Option<String>) -> Result<Spanned<Expr>, ParserError> {
+        self.expect_tok(Token::Var)?;
+        let start = self.span();
+        let pattern = self.parse_decl_pattern()?;
+        if let Some(doc) = doc {
+            let mut names = vec![];
+            pattern.names(&mut names);
+            self.docs.push(DocComment {
+                names: names.iter().map(|n| self.rodeo[*n].to_string()).collect(),
+                span: pattern.span,
+                doc,
+            });
+        }
+
+        self.expect_tok(Token::Assign)?;
+        let val = self.parse_expr()?;
+        Ok(Expr::Declaration {
+            pattern,
+            value: val.boxed(),
+        }
+        .spanned(start.extended(self.span())))
+    }
     pub fn parse_value(&mut self) -> Result<Spanned<Expr>, ParserError> {
         let mut out = self.parse_unit()?;
 
@@ -310,13 +763,32 @@ impl<'a> Parser<'a> {
                     }
                     .spanned(start_span.extended(self.span()));
                 }
+                Token::Dot => {
+                    self.next_tok();
+                    self.expect_tok_named(Token::Ident, "member name")?;
+                    let name = self.slice_intern().spanned(self.span());
+
+                    out = Expr::Member {
+                        base: out.boxed(),
+                        name,
+                    }
+                    .spanned(start_span.extended(self.span()));
+                }
                 Token::OpenParen => {
                     self.next_tok();
 
                     let mut args = vec![];
 
                     self.list_parse(Token::Comma, Token::CloseParen, |slef| {
-                        args.push(slef.parse_expr()?);
+                        if slef.skip_tok(Token::Ellipsis) {
+                            let start = slef.span();
+                            let value = slef.parse_expr()?;
+                            args.push(
+                                Expr::Spread(value.boxed()).spanned(start.extended(slef.span())),
+                            );
+                        } else {
+                            args.push(slef.parse_expr()?);
+                        }
                         Ok(())
                     })?;
 
@@ -330,6 +802,9 @@ impl<'a> Parser<'a> {
                     self.next_tok();
 
                     let pattern = PlacePattern::from_expr(out, self.rodeo)?;
+                    if matches!(pattern, PlacePattern::Array(_)) && t != Token::Assign {
+                        return Err(ParserError::InvalidAssignExpression { span: start_span });
+                    }
                     let value = self.parse_expr()?;
 
                     out = Expr::Assign {
@@ -372,8 +847,74 @@ impl<'a> Parser<'a> {
         Ok(left)
     }
     pub fn parse_expr(&mut self) -> Result<Spanned<Expr>, ParserError> {
-        self.parse_op(0)
+        let start = if self.peek_tok() == Token::DotDot {
+            None
+        } else {
+            Some(self.parse_op(0)?)
+        };
+        if !self.skip_tok(Token::DotDot) {
+            return Ok(start.unwrap());
+        }
+        let start_span = start.as_ref().map(|s| s.span).unwrap_or(self.span());
+        // `{` ends the range so `for x in 0.. {` works
+        let end = match self.peek_tok() {
+            Token::CloseSquare
+            | Token::CloseParen
+            | Token::CloseCurly
+            | Token::OpenCurly
+            | Token::Comma
+            | Token::Semicolon
+            | Token::Eof => None,
+            _ => Some(self.parse_op(0)?),
+        };
+        Ok(Expr::Range {
+            start: start.map(Box::new),
+            end: end.map(Box::new),
+        }
+        .spanned(start_span.extended(self.span())))
+    }
+    /// keeps an error to return after recovering from it
+    fn report(&mut self, err: ParserError) {
+        if self.peek_tok() == Token::Eof {
+            if self.reported_eof {
+                return;
+            }
+            self.reported_eof = true;
+        }
+        self.errors.push(err);
+    }
+    /// skips tokens until the end of the current statement, returns whether the
+    /// end of the block was also reached
+    fn synchronize(&mut self, end_tok: Token) -> bool {
+        let mut depth = 0usize;
+        loop {
+            match self.peek_tok() {
+                Token::Eof => {
+                    if end_tok != Token::Eof {
+                        self.report(ParserError::Expected {
+                            expected: format!("`{}`", end_tok.name()),
+                            found: Token::Eof,
+                            span: self.peek_span(),
+                        });
+                    }
+                    return true;
+                }
+                Token::Semicolon if depth == 0 => {
+                    self.next_tok();
+                    return self.skip_tok(end_tok);
+                }
+                t if t == end_tok && depth == 0 => {
+                    self.next_tok();
+                    return true;
+                }
+                Token::OpenCurly => depth += 1,
+                Token::CloseCurly => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+            self.next_tok();
+        }
     }
+
     /// meant to be called after passing the opening brace
     pub fn parse_block(&mut self, root: bool) -> Result<Spanned<Block>, ParserError> {
         let start = self.span();
@@ -385,27 +926,307 @@ impl<'a> Parser<'a> {
         let end_tok = if root { Token::Eof } else { Token::CloseCurly };
 
         loop {
-            let stmt = match self.peek_tok() {
-                _ => {
-                    let expr = self.parse_expr()?;
-                    let span = expr.span;
-                    Stmt::Expr(expr).spanned(span)
+            let stmt_start = self.peek_span();
+            let res = self.parse_expr().and_then(|expr| {
+                let span = expr.span;
+                let stmt = Stmt::Expr(expr).spanned(span);
+
+                if self.skip_tok(Token::Semicolon) {
+                    Ok((stmt, false))
+                } else {
+                    self.expect_tok(end_tok)?;
+                    Ok((stmt, true))
                 }
-            };
+            });
 
-            if !self.skip_tok(Token::Semicolon) {
-                self.expect_tok(end_tok)?;
-                block.ret = Some(stmt);
-                return Ok(block.spanned(start.extended(self.span())));
+            match res {
+                Ok((stmt, true)) => {
+                    block.ret = Some(stmt);
+                    return Ok(block.spanned(start.extended(self.span())));
+                }
+                Ok((stmt, false)) => {
+                    block.normal.push(stmt);
+                    if self.skip_tok(end_tok) {
+                        return Ok(block.spanned(start.extended(self.span())));
+                    }
+                }
+                Err(err) if self.recovering => {
+                    self.report(err);
+                    let ended = self.synchronize(end_tok);
+
+                    let span = stmt_start.extended(self.span());
+                    block
+                        .normal
+                        .push(Stmt::Expr(Expr::Error.spanned(span)).spanned(span));
+                    if ended {
+                        return Ok(block.spanned(start.extended(self.span())));
+                    }
+                }
+                Err(err) => return Err(err),
             }
-            block.normal.push(stmt);
-            if self.skip_tok(end_tok) {
-                return Ok(block.spanned(start.extended(self.span())));
+        }
+    }
+    /// warnings found so far, the parse still succeeds with these
+    pub fn warnings(&self) -> &[ParserWarning] {
+        &self.warnings
+    }
+    /// doc comments found so far, in source order
+    pub fn docs(&self) -> &[DocComment] {
+        &self.docs
+    }
+    /// parses the whole source, recovering from errors at `;` and `}` and
+    /// returning the partial block with every error that was found
+    pub fn parse_root_recovering(&mut self) -> (Spanned<Block>, Vec<ParserError>) {
+        self.recovering = true;
+        let block = self.parse_block(true);
+        self.recovering = false;
+
+        let mut errors = mem::take(&mut self.errors);
+        let block = match block {
+            Ok(block) => block,
+            Err(err) => {
+                errors.push(err);
+                Block {
+                    normal: vec![],
+                    ret: None,
+                }
+                .spanned(self.span())
             }
+        };
+        (block, errors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lasso::Rodeo;
+
+    use super::{
+        ast::{Block, Expr, Stmt},
+        error::ParserError,
+        lexer::Token,
+        Parser,
+    };
+    use crate::span::Span;
+
+    /// parses `src` and returns its final expression
+    fn parse_ret(src: &str) -> Expr {
+        let mut rodeo = Rodeo::new();
+        let (block, errors) = Parser::new(src, &mut rodeo).parse_root_recovering();
+        assert_eq!(errors, vec![]);
+        let Stmt::Expr(expr) = block.val.ret.unwrap().val;
+        expr.val
+    }
+
+    #[test]
+    fn unicode_escape() {
+        assert_eq!(
+            parse_ret(r#""\u{41}\u{1F600}""#),
+            Expr::String("A😀".into())
+        );
+    }
+
+    #[test]
+    fn unicode_escape_next_to_interpolation() {
+        let Expr::Interpolated(parts) = parse_ret(r#""\u{41}{1}\u{42}""#) else {
+            panic!("expected an interpolated string");
+        };
+        let parts: Vec<_> = parts.into_iter().map(|p| p.val).collect();
+        assert_eq!(
+            parts,
+            vec![
+                Expr::String("A".into()),
+                Expr::Number(1.0),
+                Expr::String("B".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn comment_in_interpolation_stays_in_string() {
+        let errors = |src| {
+            let mut rodeo = Rodeo::new();
+            Parser::new(src, &mut rodeo).parse_root_recovering().1
+        };
+        // the comment would otherwise swallow the closing quote and `}`
+        assert_eq!(
+            errors("\"{1 // }\"\n}\""),
+            vec![ParserError::Expected {
+                expected: "`}`".into(),
+                found: Token::Eof,
+                span: Span::new(8, 8),
+            }]
+        );
+        assert_eq!(
+            errors("\"{1 /* }\" */ }\""),
+            vec![ParserError::Expected {
+                expected: "`}`".into(),
+                found: Token::Unknown,
+                span: Span::new(4, 8),
+            }]
+        );
+    }
+
+    #[test]
+    fn unclosed_unicode_escape() {
+        let mut rodeo = Rodeo::new();
+        let (_, errors) = Parser::new(r#""\u{41""#, &mut rodeo).parse_root_recovering();
+        assert_eq!(errors.len(), 1);
+    }
+
+    #[test]
+    fn doc_comments() {
+        let src = "
+            /// the first
+            ///   indented
+            var a = 1;
+            var b = 2;
+            /// both
+            var [c, {d}] = [3, {d: 4}];
+        ";
+        let mut rodeo = Rodeo::new();
+        let mut parser = Parser::new(src, &mut rodeo);
+        let (_, errors) = parser.parse_root_recovering();
+        assert_eq!(errors, vec![]);
+
+        let docs: Vec<_> = parser
+            .docs()
+            .iter()
+            .map(|d| {
+                (
+                    d.get_names(),
+                    d.get_doc(),
+                    &src[d.get_span().start..d.get_span().end],
+                )
+            })
+            .collect();
+        assert_eq!(
+            docs,
+            vec![
+                (
+                    vec!["a".to_string()],
+                    "the first\n  indented".to_string(),
+                    "a"
+                ),
+                (
+                    vec!["c".to_string(), "d".to_string()],
+                    "both".to_string(),
+                    "[c, {d}]"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn trailing_dot_number() {
+        assert_eq!(parse_ret("1."), Expr::Number(1.0));
+        let Expr::BinOp(a, ..) = parse_ret("1_0. + 1") else {
+            panic!("expected a binary operation");
+        };
+        assert_eq!(a.val, Expr::Number(10.0));
+        let Expr::Range { start, end } = parse_ret("1..3") else {
+            panic!("expected a range");
+        };
+        assert_eq!(start.unwrap().val, Expr::Number(1.0));
+        assert_eq!(end.unwrap().val, Expr::Number(3.0));
+        assert!(matches!(parse_ret("1.max"), Expr::Member { .. }));
+    }
+
+    /// the source of each statement in a block, marking the `Expr::Error`s
+    /// recovery left in place of failed ones
+    fn stmts<'a>(src: &'a str, block: &Block) -> Vec<(bool, &'a str)> {
+        block
+            .normal
+            .iter()
+            .chain(block.ret.iter())
+            .map(|stmt| {
+                let Stmt::Expr(expr) = &stmt.val;
+                (
+                    expr.val == Expr::Error,
+                    &src[stmt.span.start..stmt.span.end],
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn recovers_from_several_errors() {
+        let src = "var a = ; var b = 1; c + ;\nd";
+        let mut rodeo = Rodeo::new();
+        let (block, errors) = Parser::new(src, &mut rodeo).parse_root_recovering();
+        assert_eq!(
+            errors,
+            vec![
+                ParserError::Expected {
+                    expected: "expression".into(),
+                    found: Token::Semicolon,
+                    span: Span::new(8, 9),
+                },
+                ParserError::Expected {
+                    expected: "expression".into(),
+                    found: Token::Semicolon,
+                    span: Span::new(25, 26),
+                },
+            ]
+        );
+        assert_eq!(
+            stmts(src, &block.val),
+            vec![
+                (true, "var a = ;"),
+                (false, "var b = 1"),
+                (true, "c + ;"),
+                (false, "d"),
+            ]
+        );
+        assert!(block.val.ret.is_some());
+    }
+
+    #[test]
+    fn recovers_inside_nested_block() {
+        let src = "var x = { 1 +; 2 }; x";
+        let mut rodeo = Rodeo::new();
+        let (block, errors) = Parser::new(src, &mut rodeo).parse_root_recovering();
+        assert_eq!(
+            errors,
+            vec![ParserError::Expected {
+                expected: "expression".into(),
+                found: Token::Semicolon,
+                span: Span::new(13, 14),
+            }]
+        );
+        assert_eq!(
+            stmts(src, &block.val),
+            vec![(false, "var x = { 1 +; 2 }"), (false, "x")]
+        );
+        let Stmt::Expr(decl) = &block.val.normal[0].val;
+        let Expr::Declaration { value, .. } = &decl.val else {
+            panic!("expected a declaration");
+        };
+        let Expr::Block(inner) = &value.val else {
+            panic!("expected a block");
+        };
+        assert_eq!(stmts(src, &inner.val), vec![(true, "1 +;"), (false, "2")]);
+    }
+
+    #[test]
+    fn missing_close_curly_at_eof() {
+        for (src, expected) in [
+            ("var x = { 1", "`}`"),
+            ("var x = { { 1", "`}`"),
+            ("var x = { 1 +", "expression"),
+        ] {
+            let mut rodeo = Rodeo::new();
+            let (block, errors) = Parser::new(src, &mut rodeo).parse_root_recovering();
+            assert_eq!(
+                errors,
+                vec![ParserError::Expected {
+                    expected: expected.into(),
+                    found: Token::Eof,
+                    span: Span::new(src.len(), src.len()),
+                }],
+                "{src}"
+            );
+            assert_eq!(stmts(src, &block.val), vec![(false, src)]);
         }
     }
-    // pub fn parse_root(&mut self) -> Result<Spanned<Block>, ParserError> {
-    //     let out = self.parse_block(true)?;
-    //     Ok(out)
-    // }
 }
@@ -165,12 +165,21 @@ macro_rules! operators {
 }
 
 operators! {
-    Assign: [Assign, PlusAssign, MinusAssign, MultAssign, DivAssign, ModAssign, PowAssign];
+    Assign: [
+        Assign, PlusAssign, MinusAssign, MultAssign, DivAssign, ModAssign, PowAssign,
+        AndAssign, OrAssign, BitAndAssign, BitOrAssign, BitXorAssign, ShiftLeftAssign, ShiftRightAssign
+    ];
 
     // lowest precedence
+    Left => [Or];
+    Left => [And];
     Left => [Eq, NEq, Gt, Lt, GtE, LtE];
+    Left => [BitOr];
+    Left => [BitXor];
+    Left => [BitAnd];
+    Left => [ShiftLeft, ShiftRight];
     Left => [Plus, Minus];
-    Unary => [Minus];
+    Unary => [Minus, Not, BitNot];
     Left => [Mult, Div, Mod];
     Right => [Pow];
     // highest precedence
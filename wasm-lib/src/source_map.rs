@@ -0,0 +1,85 @@
+use wasm_bindgen::prelude::*;
+
+use crate::span::Span;
+
+/// 1-based line and column, with the column counted in UTF-16 code units
+/// like the editor expects
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct LineCol {
+    pub line: usize,
+    pub col: usize,
+}
+
+#[wasm_bindgen]
+#[derive(Debug, Clone)]
+pub struct SourceMap {
+    src: String,
+    line_starts: Vec<usize>,
+}
+
+impl SourceMap {
+    fn clamp_offset(&self, offset: usize) -> usize {
+        let mut offset = offset.min(self.src.len());
+        while !self.src.is_char_boundary(offset) {
+            offset -= 1;
+        }
+        offset
+    }
+    /// 0-based index of the line containing `offset`
+    pub fn line_idx(&self, offset: usize) -> usize {
+        match self.line_starts.binary_search(&offset) {
+            Ok(i) => i,
+            Err(i) => i - 1,
+        }
+    }
+    pub fn line_count(&self) -> usize {
+        self.line_starts.len()
+    }
+    pub fn line_start(&self, line_idx: usize) -> usize {
+        self.line_starts[line_idx]
+    }
+    /// the text of a line, without its line ending
+    pub fn line_str(&self, line_idx: usize) -> &str {
+        let start = self.line_starts[line_idx];
+        let end = self
+            .line_starts
+            .get(line_idx + 1)
+            .copied()
+            .unwrap_or(self.src.len());
+        self.src[start..end].trim_end_matches(['\n', '\r'])
+    }
+    /// number of chars between the start of the line and `offset`
+    pub fn char_col(&self, offset: usize) -> usize {
+        let offset = self.clamp_offset(offset);
+        let start = self.line_starts[self.line_idx(offset)];
+        self.src[start..offset].chars().count()
+    }
+}
+
+#[wasm_bindgen]
+impl SourceMap {
+    #[wasm_bindgen(constructor)]
+    pub fn new(src: String) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(src.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Self { src, line_starts }
+    }
+
+    pub fn line_col(&self, offset: usize) -> LineCol {
+        let offset = self.clamp_offset(offset);
+        let line = self.line_idx(offset);
+        let start = self.line_starts[line];
+        LineCol {
+            line: line + 1,
+            col: self.src[start..offset].encode_utf16().count() + 1,
+        }
+    }
+    pub fn span_start(&self, span: Span) -> LineCol {
+        self.line_col(span.start)
+    }
+    pub fn span_end(&self, span: Span) -> LineCol {
+        self.line_col(span.end)
+    }
+}
@@ -0,0 +1,220 @@
+use std::{fmt::Debug, rc::Rc};
+
+use itertools::Itertools;
+use lasso::Rodeo;
+
+use crate::{
+    console_log,
+    span::{Span, Spannable},
+};
+
+use super::{
+    error::RuntimeError,
+    methods::{expect_array, expect_number, expect_wall, Args},
+    value::{Value, Wall},
+    Arity, Unwind, Vm,
+};
+
+pub type BuiltinFn = dyn Fn(&mut Vm, &Args, Span, &mut Rodeo) -> Result<Value, Unwind>;
+
+type UnaryMathFn = fn(f64) -> f64;
+type BinaryMathFn = fn(f64, f64) -> f64;
+
+pub struct BuiltinData {
+    pub name: Rc<str>,
+    pub params: Arity,
+    pub func: Box<BuiltinFn>,
+}
+impl Debug for BuiltinData {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BuiltinData")
+            .field("name", &self.name)
+            .field("params", &self.params)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Vm {
+    /// makes a value available as a variable in the root scope of scripts run
+    /// after this, replacing any global with the same name
+    pub fn register_global(&mut self, name: &str, value: Value) {
+        self.globals.retain(|(n, _)| &**n != name);
+        self.globals.push((name.into(), value));
+    }
+    pub fn register_builtin(
+        &mut self,
+        name: &str,
+        params: Arity,
+        func: impl Fn(&mut Vm, &Args, Span, &mut Rodeo) -> Result<Value, Unwind> + 'static,
+    ) {
+        self.register_global(
+            name,
+            Value::Builtin(Rc::new(BuiltinData {
+                name: name.into(),
+                params,
+                func: Box::new(func),
+            })),
+        );
+    }
+
+    pub(super) fn register_std(&mut self) {
+        self.register_builtin("print", Arity::at_least(0), |_, args, _, _| {
+            console_log!("{}", args.iter().map(|v| v.to_str()).join(" "));
+            Ok(Value::Null)
+        });
+        self.register_math();
+        self.register_random();
+        self.register_walls();
+
+        // `range(a, b)` or `range(a, b, step)`, `b` is exclusive
+        self.register_builtin("range", Arity::range(2, 3), |_, args, _, _| {
+            let start = expect_number(&args[0])?;
+            let end = expect_number(&args[1])?;
+            let step = match args.get(2) {
+                Some(arg) => expect_number(arg)?,
+                None => 1.0,
+            };
+            if step == 0.0 {
+                return Err(RuntimeError::ZeroStep { span: args[2].span }.into());
+            }
+            // computed from the index instead of summed up so fractional
+            // steps don't drift
+            let out = (0..)
+                .map(|i| start + i as f64 * step)
+                .take_while(|&n| if step > 0.0 { n < end } else { n > end })
+                .map(Value::Number)
+                .collect();
+            Ok(Value::Array(Rc::new(out)))
+        });
+    }
+
+    /// the same helpers `level_data.ts` uses to build patterns
+    fn register_walls(&mut self) {
+        self.register_builtin("wall", Arity::exact(3), |_, args, _, _| {
+            Ok(Value::Wall(Wall {
+                column: expect_number(&args[0])?,
+                pos: expect_number(&args[1])?,
+                size: expect_number(&args[2])?,
+            }))
+        });
+        self.register_builtin("rot_wall", Arity::exact(3), |_, args, _, _| {
+            let w = expect_wall(&args[0])?;
+            let amount = expect_number(&args[1])?;
+            let sides = expect_number(&args[2])?;
+            Ok(Value::Wall(Wall {
+                column: (w.column + amount) % sides,
+                ..w
+            }))
+        });
+        self.register_builtin("bump_wall", Arity::exact(2), |_, args, _, _| {
+            let w = expect_wall(&args[0])?;
+            let beats = expect_number(&args[1])?;
+            Ok(Value::Wall(Wall {
+                pos: w.pos + beats,
+                ..w
+            }))
+        });
+    }
+
+    fn register_random(&mut self) {
+        self.register_builtin("rand", Arity::exact(0), |vm, _, _, _| {
+            Ok(Value::Number(vm.rng.next_f64()))
+        });
+        // `a` inclusive, `b` exclusive
+        self.register_builtin("rand_int", Arity::exact(2), |vm, args, span, _| {
+            let a = expect_number(&args[0])?.ceil();
+            let b = expect_number(&args[1])?.ceil();
+            if b <= a {
+                return Err(RuntimeError::EmptyChoice { span }.into());
+            }
+            Ok(Value::Number(a + (vm.rng.next_f64() * (b - a)).floor()))
+        });
+        self.register_builtin("choose", Arity::exact(1), |vm, args, _, _| {
+            let arr = expect_array(&args[0])?;
+            if arr.is_empty() {
+                return Err(RuntimeError::EmptyChoice { span: args[0].span }.into());
+            }
+            let idx = (vm.rng.next_f64() * arr.len() as f64) as usize;
+            Ok(arr[idx].clone())
+        });
+        self.register_builtin("weighted_choose", Arity::exact(2), |vm, args, _, _| {
+            let arr = expect_array(&args[0])?;
+            let weights = expect_array(&args[1])?;
+            if weights.len() != arr.len() {
+                return Err(RuntimeError::LengthMismatch {
+                    expected: arr.len(),
+                    found: weights.len(),
+                    span: args[1].span,
+                }
+                .into());
+            }
+            let weights = weights
+                .iter()
+                .map(|w| expect_number(&w.clone().spanned(args[1].span)).map(|w| w.max(0.0)))
+                .collect::<Result<Vec<_>, _>>()?;
+
+            let total: f64 = weights.iter().sum();
+            if total <= 0.0 {
+                return Err(RuntimeError::EmptyChoice { span: args[1].span }.into());
+            }
+            let mut pick = vm.rng.next_f64() * total;
+            for (v, w) in arr.iter().zip(&weights) {
+                if pick < *w {
+                    return Ok(v.clone());
+                }
+                pick -= w;
+            }
+            // float rounding can leave `pick` just past the last weight
+            let last = weights.iter().rposition(|w| *w > 0.0).unwrap();
+            Ok(arr[last].clone())
+        });
+    }
+
+    fn register_math(&mut self) {
+        self.register_global("PI", Value::Number(std::f64::consts::PI));
+        self.register_global("TAU", Value::Number(std::f64::consts::TAU));
+
+        let unary: [(&str, UnaryMathFn); 11] = [
+            ("sin", f64::sin),
+            ("cos", f64::cos),
+            ("tan", f64::tan),
+            ("asin", f64::asin),
+            ("acos", f64::acos),
+            ("atan", f64::atan),
+            ("floor", f64::floor),
+            ("ceil", f64::ceil),
+            ("round", f64::round),
+            ("abs", f64::abs),
+            ("sqrt", f64::sqrt),
+        ];
+        for (name, f) in unary {
+            self.register_builtin(name, Arity::exact(1), move |_, args, _, _| {
+                Ok(Value::Number(f(expect_number(&args[0])?)))
+            });
+        }
+
+        let binary: [(&str, BinaryMathFn); 3] =
+            [("atan2", f64::atan2), ("min", f64::min), ("max", f64::max)];
+        for (name, f) in binary {
+            self.register_builtin(name, Arity::exact(2), move |_, args, _, _| {
+                Ok(Value::Number(f(
+                    expect_number(&args[0])?,
+                    expect_number(&args[1])?,
+                )))
+            });
+        }
+
+        self.register_builtin("clamp", Arity::exact(3), |_, args, _, _| {
+            let v = expect_number(&args[0])?;
+            let min = expect_number(&args[1])?;
+            let max = expect_number(&args[2])?;
+            Ok(Value::Number(v.max(min).min(max)))
+        });
+        self.register_builtin("lerp", Arity::exact(3), |_, args, _, _| {
+            let a = expect_number(&args[0])?;
+            let b = expect_number(&args[1])?;
+            let t = expect_number(&args[2])?;
+            Ok(Value::Number(a + (b - a) * t))
+        });
+    }
+}
@@ -4,7 +4,7 @@ use crate::{
     span::Span,
 };
 
-use super::value::ValueType;
+use super::{value::ValueType, Arity};
 
 make_error! {
     @kind: Error;
@@ -51,6 +51,25 @@ make_error! {
             span: Span,
         }
 
+        @title: format!("Nonexistent key");
+        @msgs: [
+            span => "Key `{}` does not exist in this dict": key;
+        ];
+        NonexistentKey {
+            key: String,
+            span: Span,
+        }
+
+        @title: format!("Unknown member");
+        @msgs: [
+            span => "{} has no member `{}`": typ.name(), name;
+        ];
+        UnknownMember {
+            typ: ValueType,
+            name: String,
+            span: Span,
+        }
+
         @title: format!("Fractional index");
         @msgs: [
             span => "Tried to index with non-integer {}": value;
@@ -88,6 +107,15 @@ make_error! {
             span: Span,
         }
 
+        @title: format!("Cannot iterate");
+        @msgs: [
+            span => "Cannot loop over {}": typ.name();
+        ];
+        CannotIterate {
+            typ: ValueType,
+            span: Span,
+        }
+
         @title: format!("Cannot call value");
         @msgs: [
             span => "Cannot call a {}": typ.name();
@@ -99,11 +127,93 @@ make_error! {
 
         @title: format!("Incorrect argument count");
         @msgs: [
-            span => "This call requires {} arguments, but received {}": correct, bad;
+            span => "This call requires {} arguments, but received {}": expected, found;
         ];
         IncorrectArgAmount {
-            correct: usize,
-            bad: usize,
+            expected: Arity,
+            found: usize,
+            span: Span,
+        }
+
+        @title: format!("Invalid argument");
+        @msgs: [
+            span => "Expected {}, found {}": expected.name(), found.name();
+        ];
+        InvalidArgument {
+            expected: ValueType,
+            found: ValueType,
+            span: Span,
+        }
+
+        @title: format!("No matching arm");
+        @msgs: [
+            span => "No arm matches the value {}": value;
+        ];
+        NoMatchingArm {
+            value: String,
+            span: Span,
+        }
+
+        @title: format!("Cannot spread");
+        @msgs: [
+            span => "Only arrays can be spread into arguments, found {}": typ.name();
+        ];
+        CannotSpread {
+            typ: ValueType,
+            span: Span,
+        }
+
+        @title: format!("Cannot destructure");
+        @msgs: [
+            span => "This pattern expects {}, found {}": expected.name(), found.name();
+        ];
+        CannotDestructure {
+            expected: ValueType,
+            found: ValueType,
+            span: Span,
+        }
+
+        @title: format!("Invalid range bound");
+        @msgs: [
+            span => "Range bounds must be numbers, found {}": typ.name();
+        ];
+        InvalidRangeBound {
+            typ: ValueType,
+            span: Span,
+        }
+
+        @title: format!("Zero step");
+        @msgs: [
+            span => "A range step can't be zero";
+        ];
+        ZeroStep {
+            span: Span,
+        }
+
+        @title: format!("Nothing to choose from");
+        @msgs: [
+            span => "There are no values to pick from here";
+        ];
+        EmptyChoice {
+            span: Span,
+        }
+
+        @title: format!("Length mismatch");
+        @msgs: [
+            span => "Expected length {}, found length {}": expected, found;
+        ];
+        LengthMismatch {
+            expected: usize,
+            found: usize,
+            span: Span,
+        }
+
+        @title: format!("Invalid pattern");
+        @msgs: [
+            span => "Patterns must return an array of walls, found {}": found.name();
+        ];
+        InvalidPattern {
+            found: ValueType,
             span: Span,
         }
 
@@ -117,6 +227,40 @@ make_error! {
             span: Span,
         }
 
+        @title: format!("Escaped control flow");
+        @msgs: [
+            span => "A `break`, `continue` or `return` escaped to the top level here";
+        ];
+        EscapedControlFlow {
+            span: Span,
+        }
+
+        @title: format!("Host function error");
+        @msgs: [
+            span => "{}": msg;
+        ];
+        HostError {
+            msg: String,
+            span: Span,
+        }
+
+        @title: format!("Function from another interpreter");
+        @msgs: [
+            span => "A function returned by another interpreter can't be passed to this one";
+        ];
+        ForeignFunction {
+            span: Span,
+        }
+
+        @title: format!("Cannot convert JS value");
+        @msgs: [
+            span => "A JS {} can't be passed to a script": typ;
+        ];
+        CannotConvertJs {
+            typ: String,
+            span: Span,
+        }
+
         // @title: format!("Cannot declare variables with special name");
         // @msgs: [
         //     span => "Variable declared here";
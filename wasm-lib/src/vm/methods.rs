@@ -0,0 +1,460 @@
+use std::{cmp::Ordering, rc::Rc};
+
+use itertools::Itertools;
+use lasso::Rodeo;
+
+use crate::{
+    parser::operators::BinOp,
+    span::{Span, Spannable, Spanned},
+};
+
+use super::{
+    error::RuntimeError,
+    value::{ops, Str, Value, ValueType, Wall},
+    Arity, Unwind, Vm,
+};
+
+pub type Args = [Spanned<Value>];
+
+pub enum MethodFn {
+    /// gets the receiver by reference so it can be modified in place
+    Mut(fn(&mut Value, &Args, Span) -> Result<Value, RuntimeError>),
+    /// gets a copy of the receiver, and the vm so it can call closures
+    Ref(fn(&mut Vm, &Value, &Args, Span, &mut Rodeo) -> Result<Value, Unwind>),
+}
+
+pub struct Method {
+    pub name: &'static str,
+    /// allowed argument counts
+    pub params: Arity,
+    pub func: MethodFn,
+}
+
+pub fn get_method(typ: ValueType, name: &str) -> Option<&'static Method> {
+    let table: &[Method] = match typ {
+        ValueType::Number => NUMBER_METHODS,
+        ValueType::String => STRING_METHODS,
+        ValueType::Array => ARRAY_METHODS,
+        _ => &[],
+    };
+    table.iter().find(|m| m.name == name)
+}
+
+pub fn expect_number(arg: &Spanned<Value>) -> Result<f64, RuntimeError> {
+    match &arg.val {
+        Value::Number(n) => Ok(*n),
+        v => Err(RuntimeError::InvalidArgument {
+            expected: ValueType::Number,
+            found: v.get_type(),
+            span: arg.span,
+        }),
+    }
+}
+pub fn expect_string(arg: &Spanned<Value>) -> Result<Str, RuntimeError> {
+    match &arg.val {
+        Value::String(s) => Ok(s.clone()),
+        v => Err(RuntimeError::InvalidArgument {
+            expected: ValueType::String,
+            found: v.get_type(),
+            span: arg.span,
+        }),
+    }
+}
+
+pub fn expect_array(arg: &Spanned<Value>) -> Result<Rc<Vec<Value>>, RuntimeError> {
+    match &arg.val {
+        Value::Array(v) => Ok(v.clone()),
+        v => Err(RuntimeError::InvalidArgument {
+            expected: ValueType::Array,
+            found: v.get_type(),
+            span: arg.span,
+        }),
+    }
+}
+
+/// an integer index into something of length `len`, negative ones count from
+/// the end
+pub fn expect_index(
+    arg: &Spanned<Value>,
+    typ: ValueType,
+    len: usize,
+    allow_end: bool,
+) -> Result<usize, RuntimeError> {
+    ops::resolve_index(expect_number(arg)?, typ, len, allow_end, arg.span)
+}
+
+/// calls a closure passed as a method argument
+fn call_arg(
+    vm: &mut Vm,
+    func: &Spanned<Value>,
+    args: Vec<Value>,
+    span: Span,
+    rodeo: &mut Rodeo,
+) -> Result<Value, Unwind> {
+    let args = args.into_iter().map(|a| a.spanned(span)).collect();
+    vm.call_value(func.val.clone(), args, span, func.span, rodeo)
+}
+
+/// default ordering used by `sort`, only numbers with numbers and strings
+/// with strings can be compared
+fn compare(a: &Value, b: &Value, span: Span) -> Result<Ordering, RuntimeError> {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => Ok(a.total_cmp(b)),
+        (Value::String(a), Value::String(b)) => Ok(a.cmp(b)),
+        _ => Err(RuntimeError::InvalidOperands {
+            type1: a.get_type(),
+            type2: b.get_type(),
+            op: BinOp::Lt,
+            span,
+        }),
+    }
+}
+
+/// a stable sort that stops at the first error, unlike `slice::sort_by` it
+/// doesn't panic when `cmp` isn't a total order
+fn merge_sort<E>(
+    mut arr: Vec<Value>,
+    cmp: &mut impl FnMut(&Value, &Value) -> Result<Ordering, E>,
+) -> Result<Vec<Value>, E> {
+    if arr.len() <= 1 {
+        return Ok(arr);
+    }
+    let right = arr.split_off(arr.len() / 2);
+    let left = merge_sort(arr, cmp)?;
+    let right = merge_sort(right, cmp)?;
+
+    let mut out = Vec::with_capacity(left.len() + right.len());
+    let mut left = left.into_iter().peekable();
+    let mut right = right.into_iter().peekable();
+    while let (Some(a), Some(b)) = (left.peek(), right.peek()) {
+        // ties take from the left to keep the sort stable
+        let next = if cmp(a, b)? == Ordering::Greater {
+            right.next()
+        } else {
+            left.next()
+        };
+        out.extend(next);
+    }
+    out.extend(left);
+    out.extend(right);
+    Ok(out)
+}
+
+pub fn expect_wall(arg: &Spanned<Value>) -> Result<Wall, RuntimeError> {
+    match &arg.val {
+        Value::Wall(w) => Ok(*w),
+        v => Err(RuntimeError::InvalidArgument {
+            expected: ValueType::Wall,
+            found: v.get_type(),
+            span: arg.span,
+        }),
+    }
+}
+
+// the tables only contain methods of the matching type, so the receiver
+// patterns below can't fail
+macro_rules! recv {
+    ($v:expr, $variant:ident) => {
+        match $v {
+            Value::$variant(v) => v,
+            _ => unreachable!(),
+        }
+    };
+}
+
+const NUMBER_METHODS: &[Method] = &[Method {
+    name: "floor",
+    params: Arity::exact(0),
+    func: MethodFn::Ref(|_, this, _, _, _| Ok(Value::Number(recv!(this, Number).floor()))),
+}];
+
+/// the string methods work on chars, not bytes
+const STRING_METHODS: &[Method] = &[
+    Method {
+        name: "len",
+        params: Arity::exact(0),
+        func: MethodFn::Ref(|_, this, _, _, _| {
+            Ok(Value::Number(recv!(this, String).char_len() as f64))
+        }),
+    },
+    Method {
+        name: "split",
+        params: Arity::exact(1),
+        func: MethodFn::Ref(|_, this, args, _, _| {
+            let sep = expect_string(&args[0])?;
+            Ok(Value::Array(Rc::new(
+                recv!(this, String)
+                    .split(&*sep)
+                    .map(|s| Value::String(s.into()))
+                    .collect(),
+            )))
+        }),
+    },
+    Method {
+        name: "trim",
+        params: Arity::exact(0),
+        func: MethodFn::Ref(|_, this, _, _, _| {
+            Ok(Value::String(recv!(this, String).trim().into()))
+        }),
+    },
+    Method {
+        name: "upper",
+        params: Arity::exact(0),
+        func: MethodFn::Ref(|_, this, _, _, _| {
+            Ok(Value::String(recv!(this, String).to_uppercase().into()))
+        }),
+    },
+    Method {
+        name: "lower",
+        params: Arity::exact(0),
+        func: MethodFn::Ref(|_, this, _, _, _| {
+            Ok(Value::String(recv!(this, String).to_lowercase().into()))
+        }),
+    },
+    Method {
+        name: "contains",
+        params: Arity::exact(1),
+        func: MethodFn::Ref(|_, this, args, _, _| {
+            let pat = expect_string(&args[0])?;
+            Ok(Value::Bool(recv!(this, String).contains(&*pat)))
+        }),
+    },
+    Method {
+        name: "starts_with",
+        params: Arity::exact(1),
+        func: MethodFn::Ref(|_, this, args, _, _| {
+            let pat = expect_string(&args[0])?;
+            Ok(Value::Bool(recv!(this, String).starts_with(&*pat)))
+        }),
+    },
+    Method {
+        name: "ends_with",
+        params: Arity::exact(1),
+        func: MethodFn::Ref(|_, this, args, _, _| {
+            let pat = expect_string(&args[0])?;
+            Ok(Value::Bool(recv!(this, String).ends_with(&*pat)))
+        }),
+    },
+    Method {
+        name: "replace",
+        params: Arity::exact(2),
+        func: MethodFn::Ref(|_, this, args, _, _| {
+            let from = expect_string(&args[0])?;
+            let to = expect_string(&args[1])?;
+            Ok(Value::String(
+                recv!(this, String).replace(&*from, &to).into(),
+            ))
+        }),
+    },
+    // char index of the first match, or null
+    Method {
+        name: "find",
+        params: Arity::exact(1),
+        func: MethodFn::Ref(|_, this, args, _, _| {
+            let pat = expect_string(&args[0])?;
+            let s = recv!(this, String);
+            Ok(match s.find(&*pat) {
+                Some(i) => Value::Number(s.char_index(i) as f64),
+                None => Value::Null,
+            })
+        }),
+    },
+    Method {
+        name: "chars",
+        params: Arity::exact(0),
+        func: MethodFn::Ref(|_, this, _, _, _| {
+            Ok(Value::Array(Rc::new(
+                recv!(this, String)
+                    .chars()
+                    .map(|c| Value::String(c.to_string().into()))
+                    .collect(),
+            )))
+        }),
+    },
+    Method {
+        name: "repeat",
+        params: Arity::exact(1),
+        func: MethodFn::Ref(|_, this, args, _, _| {
+            let n = expect_number(&args[0])?;
+            Ok(Value::String(
+                recv!(this, String)
+                    .repeat((n as i64).max(0) as usize)
+                    .into(),
+            ))
+        }),
+    },
+    Method {
+        name: "slice",
+        params: Arity::exact(2),
+        func: MethodFn::Ref(|_, this, args, _, _| {
+            let s = recv!(this, String);
+            let len = s.char_len();
+            let start = expect_index(&args[0], ValueType::String, len, true)?;
+            let end = expect_index(&args[1], ValueType::String, len, true)?.max(start);
+            Ok(Value::String(s.char_slice(start, end).unwrap().into()))
+        }),
+    },
+];
+
+const ARRAY_METHODS: &[Method] = &[
+    Method {
+        name: "len",
+        params: Arity::exact(0),
+        func: MethodFn::Ref(|_, this, _, _, _| Ok(Value::Number(recv!(this, Array).len() as f64))),
+    },
+    Method {
+        name: "push",
+        params: Arity::exact(1),
+        func: MethodFn::Mut(|this, args, _| {
+            Rc::make_mut(recv!(this, Array)).push(args[0].val.clone());
+            Ok(Value::Null)
+        }),
+    },
+    Method {
+        name: "pop",
+        params: Arity::exact(0),
+        func: MethodFn::Mut(|this, _, _| {
+            Ok(Rc::make_mut(recv!(this, Array))
+                .pop()
+                .unwrap_or(Value::Null))
+        }),
+    },
+    Method {
+        name: "insert",
+        params: Arity::exact(2),
+        func: MethodFn::Mut(|this, args, _| {
+            let arr = Rc::make_mut(recv!(this, Array));
+            let idx = expect_index(&args[0], ValueType::Array, arr.len(), true)?;
+            arr.insert(idx, args[1].val.clone());
+            Ok(Value::Null)
+        }),
+    },
+    Method {
+        name: "remove",
+        params: Arity::exact(1),
+        func: MethodFn::Mut(|this, args, _| {
+            let arr = Rc::make_mut(recv!(this, Array));
+            let idx = expect_index(&args[0], ValueType::Array, arr.len(), false)?;
+            Ok(arr.remove(idx))
+        }),
+    },
+    Method {
+        name: "slice",
+        params: Arity::exact(2),
+        func: MethodFn::Ref(|_, this, args, _, _| {
+            let arr = recv!(this, Array);
+            let start = expect_index(&args[0], ValueType::Array, arr.len(), true)?;
+            let end = expect_index(&args[1], ValueType::Array, arr.len(), true)?.max(start);
+            Ok(Value::Array(Rc::new(arr[start..end].to_vec())))
+        }),
+    },
+    // `reverse` and `sort` return a new array like the combinators below,
+    // the receiver is left as is
+    Method {
+        name: "reverse",
+        params: Arity::exact(0),
+        func: MethodFn::Ref(|_, this, _, _, _| {
+            Ok(Value::Array(Rc::new(
+                recv!(this, Array).iter().rev().cloned().collect(),
+            )))
+        }),
+    },
+    Method {
+        name: "sort",
+        params: Arity::range(0, 1),
+        func: MethodFn::Ref(|vm, this, args, span, rodeo| {
+            let arr = merge_sort(
+                recv!(this, Array).to_vec(),
+                &mut |a, b| match args.first() {
+                    Some(f) => {
+                        call_arg(vm, f, vec![a.clone(), b.clone()], span, rodeo).and_then(|v| {
+                            expect_number(&v.spanned(f.span))
+                                .map(|n| n.partial_cmp(&0.0).unwrap_or(Ordering::Equal))
+                                .map_err(Unwind::from)
+                        })
+                    }
+                    None => compare(a, b, span).map_err(Unwind::from),
+                },
+            )?;
+            Ok(Value::Array(Rc::new(arr)))
+        }),
+    },
+    Method {
+        name: "map",
+        params: Arity::exact(1),
+        func: MethodFn::Ref(|vm, this, args, span, rodeo| {
+            let out = recv!(this, Array)
+                .iter()
+                .map(|v| call_arg(vm, &args[0], vec![v.clone()], span, rodeo))
+                .collect::<Result<_, _>>()?;
+            Ok(Value::Array(Rc::new(out)))
+        }),
+    },
+    Method {
+        name: "filter",
+        params: Arity::exact(1),
+        func: MethodFn::Ref(|vm, this, args, span, rodeo| {
+            let mut out = vec![];
+            for v in recv!(this, Array).iter() {
+                let keep = call_arg(vm, &args[0], vec![v.clone()], span, rodeo)?;
+                if keep.as_bool(args[0].span)? {
+                    out.push(v.clone());
+                }
+            }
+            Ok(Value::Array(Rc::new(out)))
+        }),
+    },
+    Method {
+        name: "reduce",
+        params: Arity::exact(2),
+        func: MethodFn::Ref(|vm, this, args, span, rodeo| {
+            let mut acc = args[1].val.clone();
+            for v in recv!(this, Array).iter() {
+                acc = call_arg(vm, &args[0], vec![acc, v.clone()], span, rodeo)?;
+            }
+            Ok(acc)
+        }),
+    },
+    Method {
+        name: "flat_map",
+        params: Arity::exact(1),
+        func: MethodFn::Ref(|vm, this, args, span, rodeo| {
+            let mut out = vec![];
+            for v in recv!(this, Array).iter() {
+                match call_arg(vm, &args[0], vec![v.clone()], span, rodeo)? {
+                    Value::Array(inner) => out.extend(inner.iter().cloned()),
+                    v => out.push(v),
+                }
+            }
+            Ok(Value::Array(Rc::new(out)))
+        }),
+    },
+    Method {
+        name: "join",
+        params: Arity::exact(1),
+        func: MethodFn::Ref(|_, this, args, _, _| {
+            let sep = expect_string(&args[0])?;
+            Ok(Value::String(
+                recv!(this, Array)
+                    .iter()
+                    .map(|v| v.to_str())
+                    .join(&sep)
+                    .into(),
+            ))
+        }),
+    },
+    Method {
+        name: "zip",
+        params: Arity::exact(1),
+        func: MethodFn::Ref(|_, this, args, _, _| {
+            let other = expect_array(&args[0])?;
+            Ok(Value::Array(Rc::new(
+                recv!(this, Array)
+                    .iter()
+                    .zip(other.iter())
+                    .map(|(a, b)| Value::Array(Rc::new(vec![a.clone(), b.clone()])))
+                    .collect(),
+            )))
+        }),
+    },
+];
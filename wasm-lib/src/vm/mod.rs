@@ -1,98 +1,563 @@
 // use ahash::AHashMap;
 
-use std::{collections::HashMap, rc::Rc};
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap},
+    fmt::Display,
+    mem,
+    ops::ControlFlow,
+    rc::{Rc, Weak},
+};
 
 use error::RuntimeError;
 use lasso::{Rodeo, Spur};
+use methods::MethodFn;
+use rng::Rng;
 use value::{ops::IndexResult, FunctionData, Value, ValueType};
 
 use crate::{
     console_log,
     parser::{
-        ast::{Block, Expr, PlacePattern, Stmt},
+        ast::{Block, DeclPattern, Expr, MatchPattern, PlacePattern, Stmt},
         operators::{AssignOp, BinOp, UnaryOp},
     },
-    span::{Spannable, Spanned},
+    span::{Span, Spannable, Spanned},
 };
 
+pub mod builtins;
 pub mod error;
+pub mod methods;
+pub mod rng;
 pub mod value;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum ScopeType {
-    Normal,
-    FuncStart,
+/// whether an expression refers to a variable or part of one
+fn is_place(expr: &Spanned<Expr>) -> bool {
+    match &expr.val {
+        Expr::Ident(_) => true,
+        Expr::Index { base, .. } | Expr::Member { base, .. } => is_place(base),
+        _ => false,
+    }
+}
+
+/// checks a value against a `match` pattern, collecting the names it binds
+fn match_pattern(pattern: &MatchPattern, value: &Value, binds: &mut Vec<(Spur, Value)>) -> bool {
+    match (pattern, value) {
+        (MatchPattern::Wildcard, _) => true,
+        (MatchPattern::Bind(name), _) => {
+            binds.push((*name, value.clone()));
+            true
+        }
+        (MatchPattern::Number(a), Value::Number(b)) => a == b,
+        (MatchPattern::String(a), Value::String(b)) => **a == **b,
+        (MatchPattern::Bool(a), Value::Bool(b)) => a == b,
+        (MatchPattern::Type(t), _) => value.get_type() == *t,
+        (MatchPattern::Array(pats), Value::Array(arr)) => {
+            pats.len() == arr.len()
+                && pats
+                    .iter()
+                    .zip(arr.iter())
+                    .all(|(p, v)| match_pattern(&p.val, v, binds))
+        }
+        _ => false,
+    }
+}
+
+/// splits an array into exactly `len` values for a destructuring pattern
+fn destructure_array(value: Value, len: usize, span: Span) -> Result<Vec<Value>, RuntimeError> {
+    let Value::Array(arr) = value else {
+        return Err(RuntimeError::CannotDestructure {
+            expected: ValueType::Array,
+            found: value.get_type(),
+            span,
+        });
+    };
+    if arr.len() != len {
+        return Err(RuntimeError::LengthMismatch {
+            expected: len,
+            found: arr.len(),
+            span,
+        });
+    }
+    Ok(Rc::unwrap_or_clone(arr))
 }
 
-#[derive(Debug, Clone)]
+/// variables live behind a shared pointer so closures can capture the scopes
+/// they were created in, seeing and making changes to the same variables
+#[derive(Clone, Default)]
 pub struct Scope {
-    vars: HashMap<Spur, Value>,
-    typ: ScopeType,
+    vars: Rc<RefCell<HashMap<Spur, Value>>>,
 }
+/// why evaluation stopped early, runtime errors and control flow both unwind
+/// through `run_expr` until something handles them
+#[derive(Debug)]
+pub enum Unwind {
+    Error(RuntimeError),
+    Return(Value),
+    Break(Value),
+    Continue,
+}
+impl From<RuntimeError> for Unwind {
+    fn from(value: RuntimeError) -> Self {
+        Self::Error(value)
+    }
+}
+
+/// how many arguments a function accepts
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Arity {
+    pub min: usize,
+    /// `None` if any amount of extra arguments is accepted
+    pub max: Option<usize>,
+}
+impl Arity {
+    pub const fn exact(n: usize) -> Self {
+        Self {
+            min: n,
+            max: Some(n),
+        }
+    }
+    pub const fn range(min: usize, max: usize) -> Self {
+        Self {
+            min,
+            max: Some(max),
+        }
+    }
+    pub const fn at_least(min: usize) -> Self {
+        Self { min, max: None }
+    }
+    pub fn check(self, found: usize, span: Span) -> Result<(), RuntimeError> {
+        if found < self.min || self.max.is_some_and(|max| found > max) {
+            return Err(RuntimeError::IncorrectArgAmount {
+                expected: self,
+                found,
+                span,
+            });
+        }
+        Ok(())
+    }
+}
+impl Display for Arity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.max {
+            Some(max) if max == self.min => write!(f, "{}", max),
+            Some(max) => write!(f, "{} to {}", self.min, max),
+            None => write!(f, "at least {}", self.min),
+        }
+    }
+}
+
 pub struct Vm {
     scopes: Vec<Scope>,
+    /// every scope captured by a function. a function stored in a scope it
+    /// captured keeps that scope alive, so these are only freed when the vm is
+    /// dropped and empties them
+    captured: Vec<Weak<RefCell<HashMap<Spur, Value>>>>,
+    globals: Vec<(Rc<str>, Value)>,
+    rng: Rng,
 }
 
 impl Vm {
     pub fn new() -> Self {
-        Self { scopes: vec![] }
+        let mut vm = Self {
+            scopes: vec![],
+            captured: vec![],
+            globals: vec![],
+            rng: Rng::new(0),
+        };
+        vm.register_std();
+        vm
+    }
+    /// reseeds the generator used by `rand` and the other random builtins
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = Rng::new(seed);
     }
 
-    pub fn get_var(&mut self, name: Spur) -> Option<&mut Value> {
-        for i in self.scopes.iter_mut().rev() {
-            let v = i.vars.get_mut(&name);
-            if v.is_some() {
-                return v;
+    fn var_scope(&self, name: Spur) -> Option<&Scope> {
+        self.scopes
+            .iter()
+            .rev()
+            .find(|s| s.vars.borrow().contains_key(&name))
+    }
+    pub fn get_var(&self, name: Spur) -> Option<Value> {
+        self.var_scope(name).map(|s| s.vars.borrow()[&name].clone())
+    }
+    pub fn declare_var(&mut self, name: Spur, value: Value) {
+        self.scopes
+            .last()
+            .unwrap()
+            .vars
+            .borrow_mut()
+            .insert(name, value);
+    }
+
+    pub fn declare_pattern(
+        &mut self,
+        pattern: &Spanned<DeclPattern>,
+        value: Value,
+        rodeo: &Rodeo,
+    ) -> Result<(), RuntimeError> {
+        match &pattern.val {
+            DeclPattern::Var(name) => self.declare_var(*name, value),
+            DeclPattern::Array(elems) => {
+                for (elem, v) in
+                    elems
+                        .iter()
+                        .zip(destructure_array(value, elems.len(), pattern.span)?)
+                {
+                    self.declare_pattern(elem, v, rodeo)?;
+                }
             }
-            if i.typ == ScopeType::FuncStart {
-                break;
+            DeclPattern::Dict(fields) => {
+                let Value::Dict(dict) = value else {
+                    return Err(RuntimeError::CannotDestructure {
+                        expected: ValueType::Dict,
+                        found: value.get_type(),
+                        span: pattern.span,
+                    });
+                };
+                for field in fields {
+                    let key = &rodeo[field.val];
+                    let v = dict
+                        .get(key)
+                        .cloned()
+                        .ok_or_else(|| RuntimeError::NonexistentKey {
+                            key: key.to_string(),
+                            span: field.span,
+                        })?;
+                    self.declare_var(field.val, v);
+                }
             }
         }
-        None
+        Ok(())
     }
 
-    pub fn get_assign_pattern(
+    /// assigns to every place in a pattern, destructuring arrays
+    fn assign_pattern(
         &mut self,
         pattern: &Spanned<PlacePattern>,
+        value: Value,
         rodeo: &mut Rodeo,
-    ) -> Result<&mut Value, RuntimeError> {
-        Ok(match &pattern.val {
-            PlacePattern::Var(name) => {
-                self.get_var(*name)
-                    .ok_or_else(|| RuntimeError::NonexistentVariable {
-                        name: rodeo[*name].into(),
-                        span: pattern.span,
-                    })?
+    ) -> Result<(), Unwind> {
+        match &pattern.val {
+            PlacePattern::Array(elems) => {
+                for (elem, v) in
+                    elems
+                        .iter()
+                        .zip(destructure_array(value, elems.len(), pattern.span)?)
+                {
+                    self.assign_pattern(elem, v, rodeo)?;
+                }
+                Ok(())
             }
+            _ => self.with_place(pattern, rodeo, |place| {
+                *place = value;
+                Ok(())
+            }),
+        }
+    }
+
+    /// evaluates the index expressions of a place, returning the variable it starts from
+    fn eval_place(
+        &mut self,
+        pattern: &Spanned<PlacePattern>,
+        path: &mut Vec<(Value, Span)>,
+        rodeo: &mut Rodeo,
+    ) -> Result<Spanned<Spur>, Unwind> {
+        Ok(match &pattern.val {
+            PlacePattern::Var(name) => (*name).spanned(pattern.span),
             PlacePattern::Index { base, index } => {
                 let index_v = self.run_expr(index, rodeo)?;
-                let base_v = self.get_assign_pattern(&base, rodeo)?;
-                match value::ops::index(base_v, &index_v, pattern.span)? {
-                    IndexResult::Created(value) => {
-                        return Err(RuntimeError::InvalidAssignExpression { span: pattern.span })
+                let name = self.eval_place(base, path, rodeo)?;
+                path.push((index_v, pattern.span));
+                name
+            }
+            PlacePattern::Member { base, name: member } => {
+                let name = self.eval_place(base, path, rodeo)?;
+                path.push((Value::String(rodeo[member.val].into()), pattern.span));
+                name
+            }
+            PlacePattern::Array(_) => unreachable!("array patterns are destructured first"),
+        })
+    }
+
+    /// calls `f` with a reference to the value a place refers to
+    pub fn with_place<R>(
+        &mut self,
+        pattern: &Spanned<PlacePattern>,
+        rodeo: &mut Rodeo,
+        f: impl FnOnce(&mut Value) -> Result<R, RuntimeError>,
+    ) -> Result<R, Unwind> {
+        let mut path = vec![];
+        let name = self.eval_place(pattern, &mut path, rodeo)?;
+        self.access_place(name, &path, true, rodeo, f)
+    }
+
+    /// calls `f` with a reference to a variable indexed by `path`. when not assigning,
+    /// parts of the path that create new values (like indexing strings) are allowed
+    fn access_place<R>(
+        &self,
+        name: Spanned<Spur>,
+        path: &[(Value, Span)],
+        assign: bool,
+        rodeo: &Rodeo,
+        f: impl FnOnce(&mut Value) -> Result<R, RuntimeError>,
+    ) -> Result<R, Unwind> {
+        let scope = self
+            .var_scope(*name)
+            .ok_or_else(|| RuntimeError::NonexistentVariable {
+                name: rodeo[*name].into(),
+                span: name.span,
+            })?
+            .clone();
+        let mut vars = scope.vars.borrow_mut();
+        let mut place = vars.get_mut(&name).unwrap();
+
+        for (i, (index, span)) in path.iter().enumerate() {
+            let res = if assign && i == path.len() - 1 {
+                value::ops::index_insert(place, index, *span)?
+            } else {
+                value::ops::index(place, index, *span)?
+            };
+            place = match res {
+                IndexResult::Ref(v) => v,
+                // indexing strings, walls or slicing creates new values, so
+                // the rest is done on a copy
+                IndexResult::Created(mut v) if !assign => {
+                    for (index, span) in &path[i + 1..] {
+                        v = value::ops::get_index(&v, index, *span)?;
+                    }
+                    return Ok(f(&mut v)?);
+                }
+                IndexResult::Created(_) => {
+                    return Err(RuntimeError::InvalidAssignExpression { span: *span }.into())
+                }
+            };
+        }
+        Ok(f(place)?)
+    }
+
+    /// like `eval_place` but for expressions that `is_place` accepts
+    fn eval_expr_place(
+        &mut self,
+        expr: &Spanned<Expr>,
+        path: &mut Vec<(Value, Span)>,
+        rodeo: &mut Rodeo,
+    ) -> Result<Spanned<Spur>, Unwind> {
+        Ok(match &expr.val {
+            Expr::Ident(name) => (*name).spanned(expr.span),
+            Expr::Index { base, index } => {
+                let name = self.eval_expr_place(base, path, rodeo)?;
+                let index_v = self.run_expr(index, rodeo)?;
+                path.push((index_v, expr.span));
+                name
+            }
+            Expr::Member { base, name: member } => {
+                let name = self.eval_expr_place(base, path, rodeo)?;
+                path.push((Value::String(rodeo[member.val].into()), expr.span));
+                name
+            }
+            _ => unreachable!(),
+        })
+    }
+
+    /// calls a method on a value, or a function stored in a dict field. when the
+    /// receiver is a variable (or part of one), mutating methods modify it in place
+    fn call_method(
+        &mut self,
+        recv: &Spanned<Expr>,
+        name: Spanned<Spur>,
+        args: &[Spanned<Expr>],
+        span: Span,
+        rodeo: &mut Rodeo,
+    ) -> Result<Value, Unwind> {
+        let mut path = vec![];
+        let mut temp = None;
+        let var = if is_place(recv) {
+            Some(self.eval_expr_place(recv, &mut path, rodeo)?)
+        } else {
+            temp = Some(self.run_expr(recv, rodeo)?);
+            None
+        };
+
+        let member: Rc<str> = rodeo[name.val].into();
+        let inspect = |v: &mut Value| {
+            Ok(match v {
+                Value::Dict(d) => (ValueType::Dict, d.get(&member).cloned()),
+                _ => (v.get_type(), None),
+            })
+        };
+        let (typ, field) = match var {
+            Some(var) => self.access_place(var, &path, false, rodeo, inspect)?,
+            None => inspect(temp.as_mut().unwrap())?,
+        };
+
+        let args = self.eval_args(args, rodeo)?;
+
+        if let Some(field) = field {
+            return self.call_value(field, args, span, name.span, rodeo);
+        }
+        let method =
+            methods::get_method(typ, &member).ok_or_else(|| RuntimeError::UnknownMember {
+                typ,
+                name: member.to_string(),
+                span: name.span,
+            })?;
+        method.params.check(args.len(), span)?;
+
+        match method.func {
+            MethodFn::Mut(f) => match var {
+                Some(var) => self.access_place(var, &path, false, rodeo, |v| f(v, &args, span)),
+                None => Ok(f(temp.as_mut().unwrap(), &args, span)?),
+            },
+            MethodFn::Ref(f) => {
+                let recv = match var {
+                    Some(var) => self.access_place(var, &path, false, rodeo, |v| Ok(v.clone()))?,
+                    None => temp.unwrap(),
+                };
+                f(self, &recv, &args, span, rodeo)
+            }
+        }
+    }
+
+    /// evaluates call arguments, expanding spread arrays
+    fn eval_args(
+        &mut self,
+        args: &[Spanned<Expr>],
+        rodeo: &mut Rodeo,
+    ) -> Result<Vec<Spanned<Value>>, Unwind> {
+        let mut out = vec![];
+        for arg in args {
+            match &arg.val {
+                Expr::Spread(v) => match self.run_expr(v, rodeo)? {
+                    Value::Array(arr) => {
+                        out.extend(arr.iter().map(|v| v.clone().spanned(arg.span)))
+                    }
+                    v => {
+                        return Err(RuntimeError::CannotSpread {
+                            typ: v.get_type(),
+                            span: arg.span,
+                        }
+                        .into())
+                    }
+                },
+                _ => out.push(self.run_expr(arg, rodeo)?.spanned(arg.span)),
+            }
+        }
+        Ok(out)
+    }
+
+    /// calls a function or type conversion with already evaluated arguments
+    pub fn call_value(
+        &mut self,
+        callee: Value,
+        args: Vec<Spanned<Value>>,
+        span: Span,
+        callee_span: Span,
+        rodeo: &mut Rodeo,
+    ) -> Result<Value, Unwind> {
+        Ok(match callee {
+            Value::Function(v) => {
+                let required = v.params.iter().rposition(|(_, d)| d.is_none());
+                let arity = Arity {
+                    min: required.map_or(0, |i| i + 1),
+                    max: v.rest.is_none().then_some(v.params.len()),
+                };
+                arity.check(args.len(), span)?;
+
+                let mut args = args.into_iter().map(|a| a.val);
+                let outer = mem::replace(&mut self.scopes, v.captured.clone());
+                self.scopes.push(Scope::default());
+                // defaults are evaluated in the function's scope so they can
+                // refer to earlier parameters
+                let out = (|| {
+                    for (param, default) in v.params.iter() {
+                        let value = match (args.next(), default) {
+                            (Some(arg), _) => arg,
+                            (None, Some(default)) => self.run_expr(default, rodeo)?,
+                            (None, None) => unreachable!(),
+                        };
+                        self.declare_var(*param, value);
                     }
-                    IndexResult::Ref(v) => v,
+                    if let Some(rest) = v.rest {
+                        self.declare_var(rest, Value::Array(Rc::new(args.collect())));
+                    }
+                    self.run_expr(&v.body, rodeo)
+                })();
+                self.scopes = outer;
+                match out {
+                    Ok(v) | Err(Unwind::Return(v)) => v,
+                    Err(e) => return Err(e),
+                }
+            }
+            Value::Builtin(b) => {
+                b.params.check(args.len(), span)?;
+                (b.func)(self, &args, span, rodeo)?
+            }
+            Value::Type(t) => {
+                Arity::exact(1).check(args.len(), span)?;
+                value::ops::convert(&args[0], &t, span).ok_or_else(|| {
+                    RuntimeError::CannotConvert {
+                        from: args[0].get_type(),
+                        to: t,
+                        span,
+                    }
+                })?
+            }
+            _ => {
+                return Err(RuntimeError::CannotCall {
+                    typ: callee.get_type(),
+                    span: callee_span,
                 }
+                .into())
             }
         })
     }
 
-    pub fn run_expr(
+    /// runs one iteration of a loop, cleaning up the scopes left behind by `break`
+    /// and `continue`
+    fn run_loop_body(
         &mut self,
-        expr: &Spanned<Expr>,
+        body: &Spanned<Expr>,
         rodeo: &mut Rodeo,
-    ) -> Result<Value, RuntimeError> {
+    ) -> Result<ControlFlow<Value, Value>, Unwind> {
+        let depth = self.scopes.len();
+        let out = match self.run_expr(body, rodeo) {
+            Ok(v) => ControlFlow::Continue(v),
+            Err(Unwind::Continue) => ControlFlow::Continue(Value::Null),
+            Err(Unwind::Break(v)) => ControlFlow::Break(v),
+            Err(e) => return Err(e),
+        };
+        self.scopes.truncate(depth);
+        Ok(out)
+    }
+
+    pub fn run_expr(&mut self, expr: &Spanned<Expr>, rodeo: &mut Rodeo) -> Result<Value, Unwind> {
         Ok(match &expr.val {
             Expr::Number(n) => Value::Number(*n),
             Expr::String(s) => Value::String(s.clone()),
             Expr::Bool(b) => Value::Bool(*b),
-            Expr::Ident(name) => self
-                .get_var(*name)
-                .ok_or_else(|| RuntimeError::NonexistentVariable {
-                    name: rodeo[*name].into(),
-                    span: expr.span,
-                })?
-                .clone(),
+            Expr::Interpolated(parts) => {
+                let mut out = String::new();
+                for part in parts {
+                    out += &self.run_expr(part, rodeo)?.to_str();
+                }
+                Value::String(out.into())
+            }
+            Expr::Ident(name) => {
+                self.get_var(*name)
+                    .ok_or_else(|| RuntimeError::NonexistentVariable {
+                        name: rodeo[*name].into(),
+                        span: expr.span,
+                    })?
+            }
+            Expr::BinOp(a, op @ (BinOp::And | BinOp::Or), b) => {
+                let a_v = self.run_expr(a, rodeo)?.as_bool(a.span)?;
+                if a_v == (*op == BinOp::Or) {
+                    Value::Bool(a_v)
+                } else {
+                    Value::Bool(self.run_expr(b, rodeo)?.as_bool(b.span)?)
+                }
+            }
             Expr::BinOp(a, op, b) => {
                 let a_v = self.run_expr(&a, rodeo)?;
                 let b_v = self.run_expr(&b, rodeo)?;
@@ -109,6 +574,12 @@ impl Vm {
                     BinOp::Lt => value::ops::lt(&a_v, &b_v),
                     BinOp::GtE => value::ops::gte(&a_v, &b_v),
                     BinOp::LtE => value::ops::lte(&a_v, &b_v),
+                    BinOp::BitAnd => value::ops::bit_and(&a_v, &b_v),
+                    BinOp::BitOr => value::ops::bit_or(&a_v, &b_v),
+                    BinOp::BitXor => value::ops::bit_xor(&a_v, &b_v),
+                    BinOp::ShiftLeft => value::ops::shift_left(&a_v, &b_v),
+                    BinOp::ShiftRight => value::ops::shift_right(&a_v, &b_v),
+                    BinOp::And | BinOp::Or => unreachable!(),
                 }
                 .ok_or_else(|| RuntimeError::InvalidOperands {
                     type1: a_v.get_type(),
@@ -121,6 +592,8 @@ impl Vm {
                 let v_v = self.run_expr(&v, rodeo)?;
                 match op {
                     UnaryOp::Minus => value::ops::unary_minus(&v_v),
+                    UnaryOp::Not => value::ops::unary_not(&v_v),
+                    UnaryOp::BitNot => value::ops::unary_bit_not(&v_v),
                 }
                 .ok_or_else(|| RuntimeError::InvalidUnaryOperand {
                     typ: v_v.get_type(),
@@ -129,46 +602,142 @@ impl Vm {
                 })?
             }
             Expr::Block(spanned) => self.run_block(&spanned, false, rodeo)?,
-            Expr::Array(vec) => Value::Array(
+            Expr::Array(vec) => Value::Array(Rc::new(
                 vec.iter()
                     .map(|v| self.run_expr(v, rodeo))
                     .collect::<Result<_, _>>()?,
-            ),
+            )),
+            Expr::Range { start, end } => {
+                let mut bound = |b: &Option<Box<Spanned<Expr>>>| -> Result<_, Unwind> {
+                    Ok(match b {
+                        Some(b) => match self.run_expr(b, rodeo)? {
+                            Value::Number(n) => Some(n),
+                            v => {
+                                return Err(RuntimeError::InvalidRangeBound {
+                                    typ: v.get_type(),
+                                    span: b.span,
+                                }
+                                .into())
+                            }
+                        },
+                        None => None,
+                    })
+                };
+                Value::Range {
+                    start: bound(start)?,
+                    end: bound(end)?,
+                }
+            }
+            Expr::Dict(entries) => {
+                let mut map = BTreeMap::new();
+                for (k, v) in entries {
+                    map.insert(k.val.clone(), self.run_expr(v, rodeo)?);
+                }
+                Value::Dict(Rc::new(map))
+            }
+            Expr::Member { base, name } => {
+                let base_v = self.run_expr(base, rodeo)?;
+                match &base_v {
+                    Value::Dict(d) => d
+                        .get(&rodeo[name.val])
+                        .ok_or_else(|| RuntimeError::NonexistentKey {
+                            key: rodeo[name.val].into(),
+                            span: name.span,
+                        })?
+                        .clone(),
+                    Value::Wall(w) => {
+                        Value::Number(w.field(&rodeo[name.val]).ok_or_else(|| {
+                            RuntimeError::UnknownMember {
+                                typ: ValueType::Wall,
+                                name: rodeo[name.val].into(),
+                                span: name.span,
+                            }
+                        })?)
+                    }
+                    _ => {
+                        return Err(RuntimeError::UnknownMember {
+                            typ: base_v.get_type(),
+                            name: rodeo[name.val].into(),
+                            span: name.span,
+                        }
+                        .into())
+                    }
+                }
+            }
             Expr::Index { base, index } => {
-                let mut base_v = self.run_expr(&base, rodeo)?;
-                let index_v = self.run_expr(&index, rodeo)?;
-                value::ops::index(&mut base_v, &index_v, expr.span)?.to_owned()
+                let base_v = self.run_expr(base, rodeo)?;
+                let index_v = self.run_expr(index, rodeo)?;
+                value::ops::get_index(&base_v, &index_v, expr.span)?
+            }
+            Expr::Assign {
+                op: op @ (AssignOp::AndAssign | AssignOp::OrAssign),
+                pattern,
+                value,
+            } => {
+                // like `&&` and `||`, the right side only runs if the left side
+                // doesn't decide the result
+                let mut path = vec![];
+                let name = self.eval_place(pattern, &mut path, rodeo)?;
+                let a_v =
+                    self.access_place(name, &path, false, rodeo, |p| p.as_bool(pattern.span))?;
+                if a_v != (*op == AssignOp::OrAssign) {
+                    let b_v = self.run_expr(value, rodeo)?.as_bool(value.span)?;
+                    self.access_place(name, &path, true, rodeo, |p| {
+                        *p = Value::Bool(b_v);
+                        Ok(())
+                    })?;
+                }
+                Value::Null
             }
             Expr::Assign { op, pattern, value } => {
                 let value_v = self.run_expr(&value, rodeo)?;
-                let value_type = value_v.get_type();
-                let p_ref = self.get_assign_pattern(&pattern, rodeo)?;
-
-                let set = match op {
-                    AssignOp::Assign => Some(value_v),
-                    AssignOp::PlusAssign => value::ops::plus(p_ref, &value_v),
-                    AssignOp::MinusAssign => value::ops::minus(p_ref, &value_v),
-                    AssignOp::MultAssign => value::ops::mult(p_ref, &value_v),
-                    AssignOp::DivAssign => value::ops::div(p_ref, &value_v),
-                    AssignOp::ModAssign => value::ops::modulo(p_ref, &value_v),
-                    AssignOp::PowAssign => value::ops::pow(p_ref, &value_v),
+                if let PlacePattern::Array(_) = pattern.val {
+                    self.assign_pattern(pattern, value_v, rodeo)?;
+                    return Ok(Value::Null);
                 }
-                .ok_or_else(|| RuntimeError::InvalidOperands {
-                    type1: p_ref.get_type(),
-                    type2: value_type,
-                    op: match op {
-                        AssignOp::Assign => unreachable!(),
-                        AssignOp::PlusAssign => BinOp::Plus,
-                        AssignOp::MinusAssign => BinOp::Minus,
-                        AssignOp::MultAssign => BinOp::Mult,
-                        AssignOp::DivAssign => BinOp::Div,
-                        AssignOp::ModAssign => BinOp::Mod,
-                        AssignOp::PowAssign => BinOp::Pow,
-                    },
-                    span: expr.span,
-                })?;
+                let value_type = value_v.get_type();
+
+                self.with_place(pattern, rodeo, |p_ref| {
+                    let set = match op {
+                        AssignOp::Assign => Some(value_v),
+                        AssignOp::PlusAssign => value::ops::plus(p_ref, &value_v),
+                        AssignOp::MinusAssign => value::ops::minus(p_ref, &value_v),
+                        AssignOp::MultAssign => value::ops::mult(p_ref, &value_v),
+                        AssignOp::DivAssign => value::ops::div(p_ref, &value_v),
+                        AssignOp::ModAssign => value::ops::modulo(p_ref, &value_v),
+                        AssignOp::PowAssign => value::ops::pow(p_ref, &value_v),
+                        AssignOp::AndAssign | AssignOp::OrAssign => unreachable!(),
+                        AssignOp::BitAndAssign => value::ops::bit_and(p_ref, &value_v),
+                        AssignOp::BitOrAssign => value::ops::bit_or(p_ref, &value_v),
+                        AssignOp::BitXorAssign => value::ops::bit_xor(p_ref, &value_v),
+                        AssignOp::ShiftLeftAssign => value::ops::shift_left(p_ref, &value_v),
+                        AssignOp::ShiftRightAssign => value::ops::shift_right(p_ref, &value_v),
+                    }
+                    .ok_or_else(|| RuntimeError::InvalidOperands {
+                        type1: p_ref.get_type(),
+                        type2: value_type,
+                        op: match op {
+                            AssignOp::Assign | AssignOp::AndAssign | AssignOp::OrAssign => {
+                                unreachable!()
+                            }
+                            AssignOp::PlusAssign => BinOp::Plus,
+                            AssignOp::MinusAssign => BinOp::Minus,
+                            AssignOp::MultAssign => BinOp::Mult,
+                            AssignOp::DivAssign => BinOp::Div,
+                            AssignOp::ModAssign => BinOp::Mod,
+                            AssignOp::PowAssign => BinOp::Pow,
+                            AssignOp::BitAndAssign => BinOp::BitAnd,
+                            AssignOp::BitOrAssign => BinOp::BitOr,
+                            AssignOp::BitXorAssign => BinOp::BitXor,
+                            AssignOp::ShiftLeftAssign => BinOp::ShiftLeft,
+                            AssignOp::ShiftRightAssign => BinOp::ShiftRight,
+                        },
+                        span: expr.span,
+                    })?;
 
-                *p_ref = set;
+                    *p_ref = set;
+                    Ok(())
+                })?;
 
                 Value::Null
             }
@@ -195,13 +764,19 @@ impl Vm {
             Expr::While { cond, body } => {
                 let mut out = Value::Null;
                 while self.run_expr(&cond, rodeo)?.as_bool(cond.span)? {
-                    out = self.run_expr(&body, rodeo)?;
+                    match self.run_loop_body(body, rodeo)? {
+                        ControlFlow::Continue(v) => out = v,
+                        ControlFlow::Break(v) => {
+                            out = v;
+                            break;
+                        }
+                    }
                 }
                 out
             }
-            Expr::Declaration(name, value) => {
+            Expr::Declaration { pattern, value, .. } => {
                 let value = self.run_expr(value, rodeo)?;
-                self.scopes.last_mut().unwrap().vars.insert(*name, value);
+                self.declare_pattern(pattern, value, rodeo)?;
                 Value::Null
             }
             Expr::For {
@@ -210,84 +785,132 @@ impl Vm {
                 step,
                 body,
             } => {
-                self.scopes.push(Scope {
-                    vars: HashMap::new(),
-                    typ: ScopeType::Normal,
-                });
+                self.scopes.push(Scope::default());
                 self.run_expr(&init, rodeo)?;
 
                 let mut out = Value::Null;
                 while self.run_expr(&cond, rodeo)?.as_bool(cond.span)? {
-                    out = self.run_expr(&body, rodeo)?;
+                    match self.run_loop_body(body, rodeo)? {
+                        ControlFlow::Continue(v) => out = v,
+                        ControlFlow::Break(v) => {
+                            out = v;
+                            break;
+                        }
+                    }
                     self.run_expr(&step, rodeo)?;
                 }
 
                 self.scopes.pop();
                 out
             }
-            Expr::Function { params, body } => {
-                //
-                Value::Function(Rc::new(FunctionData {
-                    body: body.clone(),
-                    params: params.iter().map(|v| v.val).collect(),
-                }))
-            }
-            Expr::Call { base, args } => {
-                let base_v = self.run_expr(&base, rodeo)?;
-                match base_v {
-                    Value::Function(v) => {
-                        if v.params.len() != args.len() {
-                            return Err(RuntimeError::IncorrectArgAmount {
-                                correct: v.params.len(),
-                                bad: args.len(),
-                                span: expr.span,
-                            });
-                        }
-                        let mut new_scope = Scope {
-                            vars: HashMap::new(),
-                            typ: ScopeType::FuncStart,
-                        };
-                        for (param, arg) in v.params.iter().zip(args) {
-                            let val = self.run_expr(arg, rodeo)?;
-                            new_scope.vars.insert(*param, val);
+            Expr::ForIn {
+                index,
+                var,
+                iter,
+                body,
+            } => {
+                let items = self.run_expr(iter, rodeo)?.iter(iter.span)?;
+
+                let mut out = Value::Null;
+                for (i, item) in items.enumerate() {
+                    // a new scope every time so closures in the body each
+                    // capture their own element
+                    let scope = Scope::default();
+                    {
+                        let mut vars = scope.vars.borrow_mut();
+                        if let Some(index) = index {
+                            vars.insert(index.val, Value::Number(i as f64));
                         }
-                        self.scopes.push(new_scope);
-                        let out = self.run_expr(&v.body, rodeo)?;
-                        self.scopes.pop();
-                        out
+                        vars.insert(var.val, item);
                     }
-                    Value::Type(t) => {
-                        if args.len() != 1 {
-                            return Err(RuntimeError::IncorrectArgAmount {
-                                correct: 1,
-                                bad: args.len(),
-                                span: expr.span,
-                            });
+                    self.scopes.push(scope);
+                    let res = self.run_loop_body(body, rodeo);
+                    self.scopes.pop();
+                    match res? {
+                        ControlFlow::Continue(v) => out = v,
+                        ControlFlow::Break(v) => {
+                            out = v;
+                            break;
                         }
-                        let value = self.run_expr(&args[0], rodeo)?;
-                        value::ops::convert(&value, &t, expr.span).ok_or_else(|| {
-                            RuntimeError::CannotConvert {
-                                from: value.get_type(),
-                                to: t,
-                                span: expr.span,
-                            }
-                        })?
                     }
-                    _ => {
-                        return Err(RuntimeError::CannotCall {
-                            typ: base_v.get_type(),
-                            span: base.span,
-                        })
+                }
+                out
+            }
+            Expr::Match { value, arms } => {
+                let value_v = self.run_expr(value, rodeo)?;
+
+                for arm in arms {
+                    let mut binds = vec![];
+                    if !match_pattern(&arm.pattern.val, &value_v, &mut binds) {
+                        continue;
+                    }
+                    let scope = Scope::default();
+                    scope.vars.borrow_mut().extend(binds);
+                    self.scopes.push(scope);
+
+                    let depth = self.scopes.len();
+                    let res = (|| {
+                        if let Some(guard) = &arm.guard {
+                            if !self.run_expr(guard, rodeo)?.as_bool(guard.span)? {
+                                return Ok(None);
+                            }
+                        }
+                        self.run_expr(&arm.body, rodeo).map(Some)
+                    })();
+                    self.scopes.truncate(depth - 1);
+
+                    if let Some(v) = res? {
+                        return Ok(v);
                     }
                 }
+                return Err(RuntimeError::NoMatchingArm {
+                    value: value_v.to_str(),
+                    span: value.span,
+                }
+                .into());
+            }
+            Expr::Return(v) => {
+                let v = match v {
+                    Some(v) => self.run_expr(v, rodeo)?,
+                    None => Value::Null,
+                };
+                return Err(Unwind::Return(v));
+            }
+            Expr::Break(v) => {
+                let v = match v {
+                    Some(v) => self.run_expr(v, rodeo)?,
+                    None => Value::Null,
+                };
+                return Err(Unwind::Break(v));
+            }
+            Expr::Continue => return Err(Unwind::Continue),
+            Expr::Error => unreachable!("scripts with parser errors are never run"),
+            Expr::Function { params, rest, body } => {
+                // drop the scopes that were freed before growing
+                if self.captured.len() + self.scopes.len() > self.captured.capacity() {
+                    self.captured.retain(|s| s.strong_count() > 0);
+                }
+                self.captured
+                    .extend(self.scopes.iter().map(|s| Rc::downgrade(&s.vars)));
+                Value::Function(Rc::new(FunctionData {
+                    body: body.clone(),
+                    params: params.iter().map(|(p, d)| (p.val, d.clone())).collect(),
+                    rest: rest.map(|r| r.val),
+                    captured: self.scopes.clone(),
+                }))
+            }
+            Expr::Spread(_) => unreachable!("spreads are only parsed as call arguments"),
+            Expr::Call { base, args } => {
+                if let Expr::Member { base: recv, name } = &base.val {
+                    return self.call_method(recv, *name, args, expr.span, rodeo);
+                }
+                let base_v = self.run_expr(&base, rodeo)?;
+                let args = self.eval_args(args, rodeo)?;
+                self.call_value(base_v, args, expr.span, base.span, rodeo)?
             }
         })
     }
-    pub fn run_stmt(
-        &mut self,
-        stmt: &Spanned<Stmt>,
-        rodeo: &mut Rodeo,
-    ) -> Result<Value, RuntimeError> {
+    pub fn run_stmt(&mut self, stmt: &Spanned<Stmt>, rodeo: &mut Rodeo) -> Result<Value, Unwind> {
         Ok(match &stmt.val {
             Stmt::Expr(expr) => self.run_expr(expr, rodeo)?,
         })
@@ -297,24 +920,20 @@ impl Vm {
         block: &Spanned<Block>,
         root: bool,
         rodeo: &mut Rodeo,
-    ) -> Result<Value, RuntimeError> {
+    ) -> Result<Value, Unwind> {
         let mut out = Value::Null;
-        self.scopes.push(Scope {
-            vars: HashMap::new(),
-            typ: if root {
-                ScopeType::FuncStart
-            } else {
-                ScopeType::Normal
-            },
-        });
+        self.scopes.push(Scope::default());
 
         if root {
             for t in ValueType::TYPES {
-                self.scopes.last_mut().unwrap().vars.insert(
+                self.declare_var(
                     rodeo.get_or_intern(format!("${}", t.name())),
                     Value::Type(*t),
                 );
             }
+            for (name, v) in self.globals.clone() {
+                self.declare_var(rodeo.get_or_intern(&*name), v);
+            }
         }
 
         for i in block.normal.iter().chain(block.ret.iter()) {
@@ -327,4 +946,165 @@ impl Vm {
             Value::Null
         })
     }
+    /// runs the root block of a script
+    pub fn run_root(
+        &mut self,
+        block: &Spanned<Block>,
+        rodeo: &mut Rodeo,
+    ) -> Result<Value, RuntimeError> {
+        // a failed script leaves its scopes behind, and the vm may be run again
+        let depth = self.scopes.len();
+        let out = self.run_block(block, true, rodeo);
+        self.scopes.truncate(depth);
+        match out {
+            Ok(v) => Ok(v),
+            Err(Unwind::Error(e)) => Err(e),
+            // the parser rejects this, but a host crash is worse than a report
+            Err(_) => Err(RuntimeError::EscapedControlFlow { span: block.span }),
+        }
+    }
+    /// calls a script function from the host, `span` is used for errors about
+    /// the call itself
+    pub fn call_root(
+        &mut self,
+        callee: Value,
+        args: Vec<Value>,
+        span: Span,
+        rodeo: &mut Rodeo,
+    ) -> Result<Value, RuntimeError> {
+        let args = args.into_iter().map(|a| a.spanned(span)).collect();
+        let depth = self.scopes.len();
+        let out = self.call_value(callee, args, span, span, rodeo);
+        self.scopes.truncate(depth);
+        match out {
+            Ok(v) => Ok(v),
+            Err(Unwind::Error(e)) => Err(e),
+            Err(_) => Err(RuntimeError::EscapedControlFlow { span }),
+        }
+    }
+}
+
+impl Drop for Vm {
+    fn drop(&mut self) {
+        for scope in self.captured.drain(..).filter_map(|s| s.upgrade()) {
+            // taken out first, dropping the values can reach this scope again
+            let vars = mem::take(&mut *scope.borrow_mut());
+            drop(vars);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use lasso::Rodeo;
+
+    use crate::parser::Parser;
+
+    use super::{value::Value, Vm};
+
+    /// runs `src` in `vm`, returning its result as it would be printed
+    fn run(vm: &mut Vm, src: &str) -> String {
+        let mut rodeo = Rodeo::new();
+        let (ast, errors) = Parser::new(src, &mut rodeo).parse_root_recovering();
+        assert_eq!(errors, vec![]);
+        vm.run_root(&ast, &mut rodeo).unwrap().to_str()
+    }
+
+    #[test]
+    fn counters_keep_their_own_state() {
+        let src = "
+            var counter = () => {
+                var n = 0;
+                () => { n += 1; n }
+            };
+            var a = counter();
+            var b = counter();
+            a(); a();
+            [a(), b(), a()]
+        ";
+        assert_eq!(run(&mut Vm::new(), src), "[3, 1, 4]");
+    }
+
+    #[test]
+    fn closures_share_captured_variables() {
+        let src = "
+            var n = 0;
+            var inc = () => { n += 1 };
+            var get = () => n;
+            inc(); inc();
+            n += 10;
+            [get(), n]
+        ";
+        assert_eq!(run(&mut Vm::new(), src), "[12, 12]");
+    }
+
+    #[test]
+    fn mutual_recursion() {
+        let src = "
+            var is_even = (n) => if n == 0 { true } else { is_odd(n - 1) };
+            var is_odd = (n) => if n == 0 { false } else { is_even(n - 1) };
+            [is_even(10), is_odd(7), is_even(3)]
+        ";
+        assert_eq!(run(&mut Vm::new(), src), "[true, true, false]");
+    }
+
+    #[test]
+    fn recursion() {
+        let src = "
+            var fact = (n) => if n <= 1 { 1 } else { n * fact(n - 1) };
+            fact(10)
+        ";
+        assert_eq!(run(&mut Vm::new(), src), "3628800");
+    }
+
+    #[test]
+    fn same_seed_same_sequence() {
+        let src = "
+            var out = [];
+            for i in range(0, 20) {
+                out.push([
+                    rand(),
+                    rand_int(0, 100),
+                    choose([1, 2, 3]),
+                    weighted_choose([1, 2], [1, 3]),
+                ]);
+            };
+            out
+        ";
+        let seeded = |seed| {
+            let mut vm = Vm::new();
+            vm.set_seed(seed);
+            run(&mut vm, src)
+        };
+        assert_eq!(seeded(7), seeded(7));
+        assert_ne!(seeded(7), seeded(8));
+    }
+
+    #[test]
+    fn assigning_through_an_index_copies_shared_values() {
+        let src = r#"
+            var d = {a: [1]};
+            var e = d;
+            e["a"][0] = 2;
+            [d["a"][0], e["a"][0]]
+        "#;
+        assert_eq!(run(&mut Vm::new(), src), "[1, 2]");
+    }
+
+    #[test]
+    fn dropping_the_vm_frees_captured_scopes() {
+        let mut rodeo = Rodeo::new();
+        let (ast, _) = Parser::new("var f = () => f; f", &mut rodeo).parse_root_recovering();
+        let mut vm = Vm::new();
+        let Ok(Value::Function(f)) = vm.run_root(&ast, &mut rodeo) else {
+            panic!("expected a function");
+        };
+        let scope = Rc::downgrade(&f.captured.last().unwrap().vars);
+        drop(f);
+        assert!(scope.upgrade().is_some());
+        drop(vm);
+        assert!(scope.upgrade().is_none());
+    }
 }
@@ -1,14 +1,21 @@
-use std::{rc::Rc, sync::Arc};
+use std::{
+    cell::OnceCell,
+    collections::BTreeMap,
+    fmt::{Debug, Display},
+    ops::Deref,
+    rc::Rc,
+};
 
 use itertools::Itertools;
 use lasso::Spur;
+use wasm_bindgen::{JsCast, JsValue};
 
 use crate::{
     parser::ast::Expr,
     span::{Span, Spanned},
 };
 
-use super::error::RuntimeError;
+use super::{builtins::BuiltinData, error::RuntimeError, Scope};
 
 macro_rules! values {
     (
@@ -61,23 +68,73 @@ macro_rules! values {
     };
 }
 
-#[derive(Debug)]
+/// a `PatternWall` from the game, `column` is the side it's on and `pos` and
+/// `size` are in beats
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Wall {
+    pub column: f64,
+    pub pos: f64,
+    pub size: f64,
+}
+impl Wall {
+    pub fn field(&self, name: &str) -> Option<f64> {
+        Some(match name {
+            "column" => self.column,
+            "pos" => self.pos,
+            "size" => self.size,
+            _ => return None,
+        })
+    }
+    pub fn to_js(self) -> JsValue {
+        let obj = js_sys::Object::new();
+        for (k, v) in [
+            ("column", self.column),
+            ("pos", self.pos),
+            ("size", self.size),
+        ] {
+            js_sys::Reflect::set(&obj, &JsValue::from_str(k), &JsValue::from_f64(v)).unwrap();
+        }
+        obj.into()
+    }
+}
+
 pub struct FunctionData {
     pub body: Box<Spanned<Expr>>,
-    pub params: Box<[Spur]>,
+    /// names with their default values
+    pub params: Box<[(Spur, Option<Spanned<Expr>>)]>,
+    pub rest: Option<Spur>,
+    /// the scopes visible where the function was created
+    pub captured: Vec<Scope>,
+}
+impl Debug for FunctionData {
+    // the captured scopes can contain the function itself
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FunctionData")
+            .field("body", &self.body)
+            .field("params", &self.params)
+            .field("rest", &self.rest)
+            .finish_non_exhaustive()
+    }
 }
 
 values! {
     Number(f64),
     Bool(bool),
 
-    String(Rc<str>),
+    String(Str),
 
-    Array(Rc<[Value]>),
+    Array(Rc<Vec<Value>>),
+    Dict(Rc<BTreeMap<Rc<str>, Value>>),
+    Wall(Wall),
+    Range {
+        start: Option<f64>,
+        end: Option<f64>,
+    },
 
     Null,
 
     Function(Rc<FunctionData>),
+    Builtin(Rc<BuiltinData>),
 
     Type(ValueType),
 }
@@ -89,8 +146,18 @@ impl PartialEq for Value {
             (Self::Bool(l0), Self::Bool(r0)) => l0 == r0,
             (Self::String(l0), Self::String(r0)) => l0 == r0,
             (Self::Array(l0), Self::Array(r0)) => l0 == r0,
+            (Self::Dict(l0), Self::Dict(r0)) => l0 == r0,
+            (Self::Wall(l0), Self::Wall(r0)) => l0 == r0,
+            (
+                Self::Range { start, end },
+                Self::Range {
+                    start: start2,
+                    end: end2,
+                },
+            ) => start == start2 && end == end2,
             (Self::Null, Self::Null) => true,
             (Self::Function(l0), Self::Function(r0)) => Rc::ptr_eq(&l0, &r0),
+            (Self::Builtin(l0), Self::Builtin(r0)) => Rc::ptr_eq(&l0, &r0),
             (Self::Type(l0), Self::Type(r0)) => l0 == r0,
             _ => false,
         }
@@ -104,11 +171,139 @@ impl Value {
             Value::Bool(v) => v.to_string(),
             Value::String(v) => v.to_string(),
             Value::Array(v) => format!("[{}]", v.iter().map(|v| v.to_str()).join(", ")),
+            Value::Dict(v) => format!(
+                "{{{}}}",
+                v.iter()
+                    .map(|(k, v)| format!("{}: {}", k, v.to_str()))
+                    .join(", ")
+            ),
+            Value::Wall(w) => format!("wall({}, {}, {})", w.column, w.pos, w.size),
+            Value::Range { start, end } => {
+                let bound = |b: &Option<f64>| b.map(|b| b.to_string()).unwrap_or_default();
+                format!("{}..{}", bound(start), bound(end))
+            }
             Value::Null => "null".into(),
             Value::Function(data) => format!("<{}-param func>", data.params.len()),
+            Value::Builtin(data) => format!("<builtin '{}'>", data.name),
             Value::Type(value_type) => format!("<type '{}'>", value_type.name()),
         }
     }
+    pub fn to_js(&self) -> JsValue {
+        self.to_js_with(&|f| JsValue::from_str(&f.to_str()))
+    }
+    /// like `to_js`, with `function` converting functions and builtins
+    pub fn to_js_with(&self, function: &dyn Fn(&Value) -> JsValue) -> JsValue {
+        match self {
+            Value::Number(v) => JsValue::from_f64(*v),
+            Value::Bool(v) => JsValue::from_bool(*v),
+            Value::String(v) => JsValue::from_str(v),
+            Value::Array(v) => v
+                .iter()
+                .map(|v| v.to_js_with(function))
+                .collect::<js_sys::Array>()
+                .into(),
+            Value::Dict(v) => {
+                let obj = js_sys::Object::new();
+                for (k, v) in v.iter() {
+                    js_sys::Reflect::set(&obj, &JsValue::from_str(k), &v.to_js_with(function))
+                        .unwrap();
+                }
+                obj.into()
+            }
+            Value::Wall(w) => w.to_js(),
+            Value::Null => JsValue::NULL,
+            Value::Function(_) | Value::Builtin(_) => function(self),
+            Value::Range { .. } | Value::Type(_) => JsValue::from_str(&self.to_str()),
+        }
+    }
+    /// converts an argument passed in from JS, `function` gets the first look
+    /// at objects and turns the ones standing for functions back into values
+    pub fn from_js(
+        value: &JsValue,
+        span: Span,
+        function: &dyn Fn(&JsValue) -> Option<Result<Value, RuntimeError>>,
+    ) -> Result<Value, RuntimeError> {
+        if value.is_null() || value.is_undefined() {
+            return Ok(Value::Null);
+        }
+        if let Some(v) = value.as_bool() {
+            return Ok(Value::Bool(v));
+        }
+        if let Some(v) = value.as_f64() {
+            return Ok(Value::Number(v));
+        }
+        if let Some(v) = value.as_string() {
+            return Ok(Value::String(v.into()));
+        }
+        if let Some(arr) = value.dyn_ref::<js_sys::Array>() {
+            return arr
+                .iter()
+                .map(|v| Value::from_js(&v, span, function))
+                .collect::<Result<_, _>>()
+                .map(|v| Value::Array(Rc::new(v)));
+        }
+        if let Some(v) = function(value) {
+            return v;
+        }
+        if value.is_object() && !value.is_function() {
+            let mut dict = BTreeMap::new();
+            for entry in js_sys::Object::entries(value.unchecked_ref()).iter() {
+                let entry: js_sys::Array = entry.unchecked_into();
+                let key = entry.get(0).as_string().unwrap_or_default();
+                dict.insert(key.into(), Value::from_js(&entry.get(1), span, function)?);
+            }
+            return Ok(Value::Dict(Rc::new(dict)));
+        }
+        Err(RuntimeError::CannotConvertJs {
+            typ: value.js_typeof().as_string().unwrap_or_default(),
+            span,
+        })
+    }
+    /// converts a pattern's result to the game's `PatternWall[]`
+    pub fn to_js_walls(&self, span: Span) -> Result<JsValue, RuntimeError> {
+        let err = |found| RuntimeError::InvalidPattern { found, span };
+        let Value::Array(arr) = self else {
+            return Err(err(self.get_type()));
+        };
+        arr.iter()
+            .map(|v| match v {
+                Value::Wall(w) => Ok(w.to_js()),
+                v => Err(err(v.get_type())),
+            })
+            .collect::<Result<js_sys::Array, _>>()
+            .map(Into::into)
+    }
+    /// the values a `for in` loop goes through
+    pub fn iter(&self, span: Span) -> Result<Box<dyn Iterator<Item = Value>>, RuntimeError> {
+        Ok(match self {
+            Value::Array(arr) => {
+                let arr = arr.clone();
+                Box::new((0..arr.len()).map(move |i| arr[i].clone()))
+            }
+            Value::String(s) => Box::new(
+                s.chars()
+                    .map(|c| Value::String(c.to_string().into()))
+                    .collect::<Vec<_>>()
+                    .into_iter(),
+            ),
+            // open ended ranges go on until the loop breaks
+            Value::Range { start, end } => {
+                let (start, end) = (start.unwrap_or(0.0), *end);
+                Box::new(
+                    (0..)
+                        .map(move |i| start + i as f64)
+                        .take_while(move |n| end.is_none_or(|end| *n < end))
+                        .map(Value::Number),
+                )
+            }
+            v => {
+                return Err(RuntimeError::CannotIterate {
+                    typ: v.get_type(),
+                    span,
+                })
+            }
+        })
+    }
     pub fn as_bool(&self, span: Span) -> Result<bool, RuntimeError> {
         if let Value::Bool(v) = self {
             Ok(*v)
@@ -121,6 +316,95 @@ impl Value {
     }
 }
 
+/// a string value. indexing is by char, so non-ascii strings get a table of
+/// char offsets the first time they're indexed, shared by every copy
+#[derive(Clone)]
+pub struct Str(Rc<StrData>);
+struct StrData {
+    s: Box<str>,
+    /// byte offset of each char followed by the length, `None` for ascii
+    /// strings where chars and bytes line up
+    offsets: OnceCell<Option<Box<[usize]>>>,
+}
+impl Str {
+    fn offsets(&self) -> Option<&[usize]> {
+        let s = &self.0.s;
+        self.0
+            .offsets
+            .get_or_init(|| {
+                (!s.is_ascii()).then(|| {
+                    s.char_indices()
+                        .map(|(i, _)| i)
+                        .chain(std::iter::once(s.len()))
+                        .collect()
+                })
+            })
+            .as_deref()
+    }
+    /// length in chars
+    pub fn char_len(&self) -> usize {
+        match self.offsets() {
+            Some(offsets) => offsets.len() - 1,
+            None => self.len(),
+        }
+    }
+    /// byte offset of the char at `idx`, `idx == char_len()` gives `len()`
+    pub fn char_offset(&self, idx: usize) -> Option<usize> {
+        match self.offsets() {
+            Some(offsets) => offsets.get(idx).copied(),
+            None => (idx <= self.len()).then_some(idx),
+        }
+    }
+    /// index of the char starting at byte `offset`
+    pub fn char_index(&self, offset: usize) -> usize {
+        match self.offsets() {
+            Some(offsets) => offsets.partition_point(|i| *i < offset),
+            None => offset,
+        }
+    }
+    /// the chars in `start..end`
+    pub fn char_slice(&self, start: usize, end: usize) -> Option<&str> {
+        Some(&self[self.char_offset(start)?..self.char_offset(end)?])
+    }
+}
+impl Deref for Str {
+    type Target = str;
+    fn deref(&self) -> &str {
+        &self.0.s
+    }
+}
+impl From<&str> for Str {
+    fn from(s: &str) -> Self {
+        Self(Rc::new(StrData {
+            s: s.into(),
+            offsets: OnceCell::new(),
+        }))
+    }
+}
+impl From<String> for Str {
+    fn from(s: String) -> Self {
+        Self(Rc::new(StrData {
+            s: s.into(),
+            offsets: OnceCell::new(),
+        }))
+    }
+}
+impl PartialEq for Str {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+impl Debug for Str {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(&**self, f)
+    }
+}
+impl Display for Str {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(&**self, f)
+    }
+}
+
 pub mod ops {
     use std::rc::Rc;
 
@@ -145,9 +429,9 @@ pub mod ops {
         Some(match (a, b) {
             (Value::Number(a), Value::Number(b)) => Value::Number(*a + *b),
             (Value::String(a), Value::String(b)) => Value::String(format!("{}{}", a, b).into()),
-            (Value::Array(a), Value::Array(b)) => {
-                Value::Array(a.iter().cloned().chain(b.iter().cloned()).collect())
-            }
+            (Value::Array(a), Value::Array(b)) => Value::Array(Rc::new(
+                a.iter().cloned().chain(b.iter().cloned()).collect(),
+            )),
             _ => return None,
         })
     }
@@ -164,14 +448,13 @@ pub mod ops {
                 Value::String(s.repeat(repeat_int(*n)).into())
             }
             (Value::Number(n), Value::Array(arr)) | (Value::Array(arr), Value::Number(n)) => {
-                Value::Array(
+                Value::Array(Rc::new(
                     arr.iter()
                         .cloned()
                         .cycle()
                         .take(arr.len() * repeat_int(*n))
-                        .collect::<Vec<_>>()
-                        .into(),
-                )
+                        .collect(),
+                ))
             }
             _ => return None,
         })
@@ -194,6 +477,43 @@ pub mod ops {
             _ => return None,
         })
     }
+    pub fn bit_and(a: &Value, b: &Value) -> Option<Value> {
+        Some(match (a, b) {
+            (Value::Number(a), Value::Number(b)) => Value::Number((*a as i64 & *b as i64) as f64),
+            (Value::Bool(a), Value::Bool(b)) => Value::Bool(*a & *b),
+            _ => return None,
+        })
+    }
+    pub fn bit_or(a: &Value, b: &Value) -> Option<Value> {
+        Some(match (a, b) {
+            (Value::Number(a), Value::Number(b)) => Value::Number((*a as i64 | *b as i64) as f64),
+            (Value::Bool(a), Value::Bool(b)) => Value::Bool(*a | *b),
+            _ => return None,
+        })
+    }
+    pub fn bit_xor(a: &Value, b: &Value) -> Option<Value> {
+        Some(match (a, b) {
+            (Value::Number(a), Value::Number(b)) => Value::Number((*a as i64 ^ *b as i64) as f64),
+            (Value::Bool(a), Value::Bool(b)) => Value::Bool(*a ^ *b),
+            _ => return None,
+        })
+    }
+    pub fn shift_left(a: &Value, b: &Value) -> Option<Value> {
+        Some(match (a, b) {
+            (Value::Number(a), Value::Number(b)) => {
+                Value::Number((*a as i64).wrapping_shl(*b as u32) as f64)
+            }
+            _ => return None,
+        })
+    }
+    pub fn shift_right(a: &Value, b: &Value) -> Option<Value> {
+        Some(match (a, b) {
+            (Value::Number(a), Value::Number(b)) => {
+                Value::Number((*a as i64).wrapping_shr(*b as u32) as f64)
+            }
+            _ => return None,
+        })
+    }
     pub fn eq(a: &Value, b: &Value) -> Option<Value> {
         Some(Value::Bool(a == b))
     }
@@ -231,62 +551,110 @@ pub mod ops {
             _ => return None,
         })
     }
+    pub fn unary_not(v: &Value) -> Option<Value> {
+        Some(match v {
+            Value::Bool(a) => Value::Bool(!a),
+            _ => return None,
+        })
+    }
+    pub fn unary_bit_not(v: &Value) -> Option<Value> {
+        Some(match v {
+            Value::Number(a) => Value::Number(!(*a as i64) as f64),
+            _ => return None,
+        })
+    }
 
     #[derive(Debug)]
     pub enum IndexResult<'a> {
         Created(Value),
         Ref(&'a mut Value),
     }
-    impl<'a> IndexResult<'a> {
-        pub fn to_owned(self) -> Value {
-            match self {
-                IndexResult::Created(value) => value,
-                IndexResult::Ref(v) => v.clone(),
-            }
+    /// turns a possibly negative index into an offset, `len` itself is only
+    /// valid if `allow_end` is set
+    pub fn resolve_index(
+        idx: f64,
+        typ: ValueType,
+        len: usize,
+        allow_end: bool,
+        span: Span,
+    ) -> Result<usize, RuntimeError> {
+        if idx.fract() != 0.0 {
+            return Err(RuntimeError::FractionalIndex { value: idx, span });
         }
+        let resolved = if idx < 0.0 { idx + len as f64 } else { idx };
+        let max = if allow_end {
+            len as f64
+        } else {
+            len as f64 - 1.0
+        };
+        if resolved < 0.0 || resolved > max {
+            return Err(RuntimeError::IndexOutOfBounds {
+                idx: idx as i64,
+                typ,
+                length: len,
+                span,
+            });
+        }
+        Ok(resolved as usize)
     }
-    pub fn index<'a, 'b>(
-        base: &'a mut Value,
-        idx: &'b Value,
+    /// the offsets a range covers, a missing start or end means the start or
+    /// end of the value
+    pub fn resolve_range(
+        start: Option<f64>,
+        end: Option<f64>,
+        typ: ValueType,
+        len: usize,
         span: Span,
-    ) -> Result<IndexResult<'a>, RuntimeError> {
+    ) -> Result<std::ops::Range<usize>, RuntimeError> {
+        let start = match start {
+            Some(s) => resolve_index(s, typ, len, true, span)?,
+            None => 0,
+        };
+        let end = match end {
+            Some(e) => resolve_index(e, typ, len, true, span)?,
+            None => len,
+        };
+        Ok(start..end.max(start))
+    }
+
+    /// reads `base[idx]` without copying a shared array or dict
+    pub fn get_index(base: &Value, idx: &Value, span: Span) -> Result<Value, RuntimeError> {
         Ok(match (base, idx) {
             (Value::String(s), Value::Number(idx)) => {
-                if idx.fract() != 0.0 {
-                    return Err(RuntimeError::FractionalIndex { value: *idx, span });
-                }
-                if *idx >= 0.0 {
-                    let idx = *idx as usize;
-                    if let Some(c) = s.chars().nth(idx) {
-                        return Ok(IndexResult::Created(Value::String(c.to_string().into())));
-                    }
-                }
-                return Err(RuntimeError::IndexOutOfBounds {
-                    idx: *idx as i64,
-                    typ: ValueType::String,
-                    length: s.chars().count(),
-                    span,
-                });
+                let idx = resolve_index(*idx, ValueType::String, s.char_len(), false, span)?;
+                Value::String(s.char_slice(idx, idx + 1).unwrap().into())
+            }
+            (Value::String(s), Value::Range { start, end }) => {
+                let range = resolve_range(*start, *end, ValueType::String, s.char_len(), span)?;
+                Value::String(s.char_slice(range.start, range.end).unwrap().into())
             }
             (Value::Array(v), Value::Number(idx)) => {
-                if idx.fract() != 0.0 {
-                    return Err(RuntimeError::FractionalIndex { value: *idx, span });
+                let idx = resolve_index(*idx, ValueType::Array, v.len(), false, span)?;
+                v[idx].clone()
+            }
+            (Value::Array(v), Value::Range { start, end }) => {
+                let range = resolve_range(*start, *end, ValueType::Array, v.len(), span)?;
+                Value::Array(Rc::new(v[range].to_vec()))
+            }
+            (Value::Wall(w), Value::String(key)) => match w.field(key) {
+                Some(v) => Value::Number(v),
+                None => {
+                    return Err(RuntimeError::UnknownMember {
+                        typ: ValueType::Wall,
+                        name: key.to_string(),
+                        span,
+                    })
                 }
-                let len = v.len();
-                if *idx >= 0.0 {
-                    let idx = *idx as usize;
-
-                    if let Some(c) = Rc::make_mut(v).get_mut(idx) {
-                        return Ok(IndexResult::Ref(c));
-                    }
+            },
+            (Value::Dict(d), Value::String(key)) => match d.get(&**key) {
+                Some(v) => v.clone(),
+                None => {
+                    return Err(RuntimeError::NonexistentKey {
+                        key: key.to_string(),
+                        span,
+                    })
                 }
-                return Err(RuntimeError::IndexOutOfBounds {
-                    idx: *idx as i64,
-                    typ: ValueType::Array,
-                    length: len,
-                    span,
-                });
-            }
+            },
             (a, b) => {
                 return Err(RuntimeError::CannotIndex {
                     type1: a.get_type(),
@@ -297,6 +665,47 @@ pub mod ops {
         })
     }
 
+    /// like `get_index`, but array elements and dict values are borrowed
+    /// mutably, making `base` unique first
+    pub fn index<'a>(
+        base: &'a mut Value,
+        idx: &Value,
+        span: Span,
+    ) -> Result<IndexResult<'a>, RuntimeError> {
+        let borrowed = match (&*base, idx) {
+            (Value::Array(_), Value::Number(_)) => true,
+            (Value::Dict(d), Value::String(key)) => d.contains_key(&**key),
+            _ => false,
+        };
+        if !borrowed {
+            return get_index(base, idx, span).map(IndexResult::Created);
+        }
+        Ok(IndexResult::Ref(match (base, idx) {
+            (Value::Array(v), Value::Number(idx)) => {
+                let idx = resolve_index(*idx, ValueType::Array, v.len(), false, span)?;
+                &mut Rc::make_mut(v)[idx]
+            }
+            (Value::Dict(d), Value::String(key)) => Rc::make_mut(d).get_mut(&**key).unwrap(),
+            _ => unreachable!(),
+        }))
+    }
+
+    /// like `index`, but missing dict keys get added so they can be assigned to
+    pub fn index_insert<'a>(
+        base: &'a mut Value,
+        idx: &Value,
+        span: Span,
+    ) -> Result<IndexResult<'a>, RuntimeError> {
+        match (base, idx) {
+            (Value::Dict(d), Value::String(key)) => Ok(IndexResult::Ref(
+                Rc::make_mut(d)
+                    .entry((&**key).into())
+                    .or_insert(Value::Null),
+            )),
+            (base, idx) => index(base, idx, span),
+        }
+    }
+
     pub fn convert<'a, 'b>(value: &Value, to: &ValueType, span: Span) -> Option<Value> {
         Some(match (value, to) {
             (_, _) if value.get_type() == *to => value.clone(),